@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use suffix::SuffixTable;
+
+use crate::matching::MatchIndex;
+use crate::preprocessing;
+
+/// The highest ARS that any encoding of a protein can reach against one host.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bound {
+    pub ars: f64,
+    // Whether this is the optimum itself, rather than an upper bound on it.
+    pub exact: bool,
+}
+
+pub fn ars_bound(condensed: &str, index: &MatchIndex, codes: &[Vec<u8>], max_states: usize) -> Bound {
+    // Finds the best total match length by dynamic programming from the last codon back. The match
+    // lengths from a codon onwards depend only on the codons chosen from there, and reading them
+    // backwards, the match index's state and match length after each codon determine how the
+    // matches continue, so only the best total for each state and length need be kept.
+    let n = codes.len();
+    let mut totals: HashMap<(usize, usize), usize> = HashMap::new();
+    totals.insert((0, 0), 0);
+    for idx in (0..n).rev() {
+        let mut next: HashMap<(usize, usize), usize> = HashMap::new();
+        for ((state, length), total) in &totals {
+            for code in &codes[idx] {
+                let (s, l) = index.step(*state, *length, *code);
+                let best = next.entry((s, l)).or_insert(0);
+                *best = (*best).max(total + l);
+            }
+        }
+        if next.len() > max_states {
+            // Too many to keep: the codons from here back can match no further than the longest
+            // host substrings starting at each, all of which are in the condensed encoding.
+            info!("ARS bound: {} match states at codon {}, bounding the rest", next.len(), idx + 1);
+            let best = totals.values().max().unwrap();
+            let table = SuffixTable::new(condensed);
+            let rest: usize = preprocessing::longest_matches(&table, codes)[..=idx].iter().sum();
+            return Bound {
+                ars: (best + rest) as f64 / n as f64,
+                exact: false,
+            };
+        }
+        totals = next;
+    }
+    Bound {
+        ars: *totals.values().max().unwrap() as f64 / n as f64,
+        exact: true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matching::MatchingStatistics;
+
+    #[test]
+    fn test_ars_bound() {
+        // Two amino acids, each with two codons.
+        let map = c! {i.0 => i.1.to_vec(), for i in [("K", ["a", "b"]), ("L", ["c", "d"])]};
+        let host = "abcdacbd|cadbbc|ddaacb|bcadac|";
+        let protein = "KLKKLLKLKK";
+        let table = preprocessing::host_table(host.to_string());
+        let condensed = preprocessing::condensed_text(&table, protein, &map);
+        let index = MatchIndex::new(&condensed);
+        let codes = c![c![j.as_bytes()[0], for j in &map[&protein[i..=i]]], for i in 0..protein.len()];
+
+        // Try every encoding of the protein.
+        let best = (0..1 << protein.len())
+            .map(|i: usize| {
+                let encoding = c![codes[j][(i >> j) & 1] as char, for j in 0..protein.len()];
+                let lengths = index.match_lengths(&encoding.into_iter().collect::<String>());
+                lengths.iter().sum::<usize>() as f64 / protein.len() as f64
+            })
+            .fold(f64::NAN, f64::max);
+        assert_eq!(ars_bound(&condensed, &index, &codes, 1000), Bound { ars: best, exact: true });
+
+        // Tracking too few states gives an upper bound instead.
+        let bound = ars_bound(&condensed, &index, &codes, 2);
+        assert!(!bound.exact && bound.ars >= best);
+    }
+}
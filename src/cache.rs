@@ -0,0 +1,93 @@
+use std::borrow::Borrow;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// A bounded, thread-safe cache keyed by encoded sequence. Once full, the oldest entries are
+/// evicted first. A capacity of zero disables the cache.
+pub struct Cache<K, T> {
+    capacity: usize,
+    entries: Mutex<(HashMap<K, T>, VecDeque<K>)>,
+    hits: AtomicUsize,
+    lookups: AtomicUsize,
+}
+
+impl<K: Clone + Eq + Hash, T: Clone> Cache<K, T> {
+    pub fn new(capacity: usize) -> Cache<K, T> {
+        Cache {
+            capacity,
+            entries: Mutex::new((HashMap::new(), VecDeque::new())),
+            hits: AtomicUsize::new(0),
+            lookups: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn get<Q: ?Sized + Eq + Hash>(&self, key: &Q) -> Option<T>
+    where
+        K: Borrow<Q>,
+    {
+        if self.capacity == 0 {
+            return None;
+        }
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        let value = self.entries.lock().unwrap().0.get(key).cloned();
+        if value.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        }
+        value
+    }
+
+    pub fn insert(&self, key: K, value: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.0.contains_key(&key) {
+            return;
+        }
+        if entries.1.len() == self.capacity {
+            let oldest = entries.1.pop_front().unwrap();
+            entries.0.remove(&oldest);
+        }
+        entries.1.push_back(key.clone());
+        entries.0.insert(key, value);
+    }
+
+    // The number of hits and lookups so far.
+    pub fn counts(&self) -> (usize, usize) {
+        (self.hits.load(Ordering::Relaxed), self.lookups.load(Ordering::Relaxed))
+    }
+}
+
+// The proportion of lookups that were hits, or zero if there were none.
+pub fn hit_rate(hits: usize, lookups: usize) -> f64 {
+    if lookups == 0 {
+        0.0
+    } else {
+        hits as f64 / lookups as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache() {
+        let cache: Cache<String, f64> = Cache::new(2);
+        cache.insert("AAAA".to_string(), 1.0);
+        cache.insert("BBBB".to_string(), 2.0);
+        assert_eq!(cache.get("AAAA"), Some(1.0));
+        // The oldest entry is evicted to make room.
+        cache.insert("CCCC".to_string(), 3.0);
+        assert_eq!(cache.get("AAAA"), None);
+        assert_eq!(cache.get("CCCC"), Some(3.0));
+        assert_eq!(cache.counts(), (2, 3));
+
+        let disabled: Cache<String, f64> = Cache::new(0);
+        disabled.insert("AAAA".to_string(), 1.0);
+        assert_eq!(disabled.get("AAAA"), None);
+        assert_eq!(disabled.counts(), (0, 0));
+    }
+}
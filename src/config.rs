@@ -0,0 +1,553 @@
+use clap::ArgMatches;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use yaml_rust::{Yaml, YamlLoader};
+
+/// A host organism to optimise for.
+#[derive(Debug, Clone)]
+pub struct Host {
+    pub name: String,
+    pub cds: String,
+    pub weight: Option<f64>,
+    // IDs of the records in the CDS file to use. All records are used if empty.
+    pub genes: Vec<String>,
+    // Table of tRNA anticodons and their gene copy numbers, for the tAI.
+    pub trna: Option<String>,
+}
+
+/// Settings for a run, resolved from defaults, an optional config file and the command line.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub protein: String,
+    pub hosts: Vec<Host>,
+    pub table: u32,
+    // Approximate memory, in megabytes, within which to index each host's CDSs, a chunk at a time.
+    pub memory_limit: Option<usize>,
+    pub crossovers: usize,
+    pub mutations: usize,
+    pub generations: usize,
+    pub gen_start: usize,
+    pub seeds: Vec<String>,
+    pub seed_fraction: f64,
+    pub random_seed: u64,
+    pub stall: Option<usize>,
+    // Number of scored sequences to cache (0 to disable), and whether to remove duplicate candidates.
+    pub cache_size: usize,
+    pub dedup: bool,
+    // Number of islands, how often and how many candidates migrate between them, and the topology
+    // (ring or full) they migrate along.
+    pub islands: usize,
+    pub migration_interval: usize,
+    pub migrants: usize,
+    pub topology: String,
+    // How to keep the population diverse (none, crowding or sharing), the niche radius in codons for
+    // fitness sharing, and the diversity below which an island restarts.
+    pub diversity: String,
+    pub sharing_radius: usize,
+    pub restart_diversity: Option<f64>,
+    // The optimiser (ea, annealing or eda), the starting temperature and cooling schedule
+    // (geometric or linear) for annealing, and the learning rate for the eda.
+    pub optimiser: String,
+    pub temperature: f64,
+    pub cooling: String,
+    pub cooling_rate: f64,
+    pub learning_rate: f64,
+    // Whether to find the best ARS any encoding could reach against each host, and the most match
+    // states to track before settling for an upper bound.
+    pub bound: bool,
+    pub bound_states: usize,
+    pub method: String,
+    // Objectives to optimise, their weights, and whether to select by their weighted sum or Pareto dominance.
+    pub objectives: Vec<String>,
+    pub objective_weights: Vec<f64>,
+    pub objective_mode: String,
+    // Minimum scores against every host (OBJECTIVE=MIN), and how to enforce them.
+    pub thresholds: Vec<String>,
+    pub threshold_mode: String,
+    pub threshold_penalty: f64,
+    // The 5' UTR, and the window around the start codon (START,END) whose folding energy is scored.
+    pub utr: String,
+    pub mfe_window: (i64, i64),
+    // The native CDSs and source organism CDSs to harmonise with, and what to match.
+    pub native: Option<String>,
+    pub source: Option<String>,
+    pub harmony_mode: String,
+    // Restriction enzymes or recognition sequences to keep out of designs, and how to do so.
+    pub sites: Vec<String>,
+    pub site_mode: String,
+    pub site_penalty: f64,
+    // Forbidden motifs (built-in names or regular expressions), required motifs, and how to enforce them.
+    pub motifs: Vec<String>,
+    pub max_homopolymer: Option<usize>,
+    pub required_motifs: Vec<String>,
+    pub motif_mode: String,
+    pub motif_penalty: f64,
+    // GC content bounds for the whole design (MIN,MAX) and for sliding windows (SIZE,MIN,MAX).
+    pub gc: Vec<String>,
+    pub gc_window: Vec<String>,
+    pub gc_mode: String,
+    pub gc_penalty: f64,
+    // Shortest direct, inverted or palindromic repeat to keep out of designs, and how to do so.
+    pub repeat_length: Option<usize>,
+    pub repeat_mode: String,
+    pub repeat_penalty: f64,
+    // A CDS encoding the protein, the codon ranges (e.g. 1-6) to keep from it, and a mask with a 1
+    // for each codon to keep.
+    pub template: Option<String>,
+    pub lock: Vec<String>,
+    pub lock_mask: Option<String>,
+    pub outfile: String,
+    pub report: Option<String>,
+    pub trace: Option<String>,
+    pub summary: Option<String>,
+    pub top: usize,
+    pub min_distance: usize,
+}
+
+fn yaml_to_string(yaml: &Yaml) -> Option<String> {
+    // Converts a scalar YAML value to a string, as if it had been given on the command line.
+    match yaml {
+        Yaml::String(t) | Yaml::Real(t) => Some(t.clone()),
+        Yaml::Integer(t) => Some(t.to_string()),
+        Yaml::Boolean(t) => Some(t.to_string()),
+        _ => None,
+    }
+}
+
+fn yaml_to_strings(yaml: &Yaml) -> Option<Vec<String>> {
+    // Converts a YAML list, or a single scalar, to a list of strings.
+    match yaml {
+        Yaml::Array(t) => Some(t.iter().filter_map(yaml_to_string).collect()),
+        Yaml::BadValue => None,
+        _ => yaml_to_string(yaml).map(|i| vec![i]),
+    }
+}
+
+fn host_name(path: &str) -> String {
+    // Names a host after the file stem of its CDS file.
+    Path::new(path)
+        .file_stem()
+        .map_or(path.to_string(), |i| i.to_string_lossy().to_string())
+}
+
+struct Resolver<'a> {
+    matches: &'a ArgMatches<'a>,
+    config: Yaml,
+}
+
+impl<'a> Resolver<'a> {
+    // Command line values take precedence over the config file, which takes precedence over defaults.
+    fn value(&self, arg: &str, section: &str, key: &str) -> Option<String> {
+        if self.matches.occurrences_of(arg) > 0 {
+            return self.matches.value_of(arg).map(|i| i.to_string());
+        }
+        let node = if section.is_empty() {
+            &self.config[key]
+        } else {
+            &self.config[section][key]
+        };
+        yaml_to_string(node).or_else(|| self.matches.value_of(arg).map(|i| i.to_string()))
+    }
+
+    fn values(&self, arg: &str, section: &str, key: &str) -> Vec<String> {
+        if self.matches.occurrences_of(arg) > 0 {
+            return self
+                .matches
+                .values_of(arg)
+                .map_or(vec![], |i| i.map(|j| j.to_string()).collect());
+        }
+        yaml_to_strings(&self.config[section][key]).unwrap_or_default()
+    }
+
+    fn parse<T: std::str::FromStr>(&self, arg: &str, section: &str, key: &str) -> Option<T> {
+        self.value(arg, section, key).map(|i| match i.parse::<T>() {
+            Ok(t) => t,
+            Err(_) => panic!("Invalid value for {}: {}", arg, i),
+        })
+    }
+
+    fn required<T: std::str::FromStr>(&self, arg: &str, section: &str, key: &str) -> T {
+        match self.parse(arg, section, key) {
+            Some(t) => t,
+            None => panic!("A value for {} must be given on the command line or in the config file", arg),
+        }
+    }
+
+    fn flag(&self, arg: &str, section: &str, key: &str) -> bool {
+        // A flag is set on the command line, or with true in the config file unless it is turned off
+        // on the command line by the flag's name with no_ before it.
+        if self.matches.is_present(format!("no_{}", arg)) {
+            return false;
+        }
+        self.matches.is_present(arg) || self.parse(arg, section, key).unwrap_or(false)
+    }
+
+    fn hosts(&self) -> Vec<Host> {
+        // Hosts given on the command line replace those in the config file.
+        let mut hosts = if self.matches.occurrences_of("cds") > 0 {
+            c![Host {
+                name: host_name(i),
+                cds: i.to_string(),
+                weight: None,
+                genes: vec![],
+                trna: None,
+            }, for i in self.matches.values_of("cds").unwrap()]
+        } else {
+            match &self.config["hosts"] {
+                Yaml::Array(t) => c![Host {
+                    name: yaml_to_string(&i["name"]).unwrap_or_else(|| host_name(&yaml_to_string(&i["cds"]).unwrap())),
+                    cds: match yaml_to_string(&i["cds"]) {
+                        Some(t) => t,
+                        None => panic!("Each host in the config file must give a cds file"),
+                    },
+                    weight: yaml_to_string(&i["weight"]).map(|j| match j.parse::<f64>() {
+                        Ok(t) => t,
+                        Err(_) => panic!("Invalid value for weight: {}", j),
+                    }),
+                    genes: yaml_to_strings(&i["genes"]).unwrap_or_default(),
+                    trna: yaml_to_string(&i["trna"]),
+                }, for i in t],
+                _ => vec![],
+            }
+        };
+        if hosts.is_empty() {
+            panic!("At least one host CDS file must be given on the command line or in the config file");
+        }
+
+        // Weights given on the command line replace those in the config file.
+        if let Some(t) = self.matches.value_of("weights") {
+            let weights = t
+                .split(',')
+                .filter_map(|s| s.parse::<f64>().ok())
+                .collect::<Vec<_>>();
+            if weights.len() != hosts.len() {
+                panic!("Length of weights and number of organisms given must be equal in weighted mode")
+            }
+            for (idx, host) in hosts.iter_mut().enumerate() {
+                host.weight = Some(weights[idx]);
+            }
+        }
+
+        // As do tRNA tables.
+        if let Some(t) = self.matches.values_of("trna") {
+            let tables = t.collect::<Vec<&str>>();
+            if tables.len() != hosts.len() {
+                panic!("Number of tRNA tables and number of organisms given must be equal")
+            }
+            for (idx, host) in hosts.iter_mut().enumerate() {
+                host.trna = Some(tables[idx].to_string());
+            }
+        }
+        hosts
+    }
+}
+
+impl Settings {
+    pub fn from_matches(matches: &ArgMatches) -> Settings {
+        // Load the config file, if one was given.
+        let config = match matches.value_of("config") {
+            Some(t) => {
+                let text = fs::read_to_string(t).unwrap();
+                let mut docs = YamlLoader::load_from_str(&text).unwrap();
+                if docs.is_empty() {
+                    Yaml::Null
+                } else {
+                    docs.remove(0)
+                }
+            }
+            None => Yaml::Null,
+        };
+        Settings::from_config(matches, config)
+    }
+
+    fn from_config(matches: &ArgMatches, config: Yaml) -> Settings {
+        let resolver = Resolver { matches, config };
+
+        let seed_fraction: f64 = resolver.required("seed_fraction", "ea", "seed_fraction");
+        if !(0.0..=1.0).contains(&seed_fraction) {
+            panic!("Seed fraction must be between 0 and 1.");
+        }
+        let top: usize = resolver.required("top", "outputs", "top");
+        if top == 0 {
+            panic!("At least one design must be reported.");
+        }
+
+        let objectives = resolver.values("objectives", "objectives", "objectives");
+        let objectives = if objectives.is_empty() {
+            vec!["ars".to_string()]
+        } else {
+            objectives
+        };
+        let objective_weights = c![match i.parse::<f64>() {
+            Ok(t) => t,
+            Err(_) => panic!("Invalid value for objective_weights: {}", i),
+        }, for i in resolver.values("objective_weights", "objectives", "objective_weights")];
+        let objective_weights = if objective_weights.is_empty() {
+            vec![1.0; objectives.len()]
+        } else {
+            objective_weights
+        };
+
+        let mfe_window = c![match i.parse::<i64>() {
+            Ok(t) => t,
+            Err(_) => panic!("Invalid value for mfe_window: {}", i),
+        }, for i in resolver.values("mfe_window", "objectives", "mfe_window")];
+        let mfe_window = match mfe_window.len() {
+            0 => (-20, 50),
+            2 => (mfe_window[0], mfe_window[1]),
+            _ => panic!("The 5' window must be given as START,END"),
+        };
+
+        Settings {
+            protein: resolver.required("protein", "", "protein"),
+            hosts: resolver.hosts(),
+            table: resolver.required("table", "", "table"),
+            memory_limit: resolver.parse("memory_limit", "", "memory_limit"),
+            crossovers: resolver.required("crossovers", "ea", "crossovers"),
+            mutations: resolver.required("mutations", "ea", "mutations"),
+            generations: resolver.required("generations", "ea", "generations"),
+            gen_start: resolver.required("generation_start", "ea", "gen_start"),
+            seeds: resolver.values("seeds", "ea", "seeds"),
+            seed_fraction,
+            random_seed: resolver
+                .parse("random_seed", "ea", "random_seed")
+                .unwrap_or_else(rand::random::<u64>),
+            stall: resolver.parse("stall", "ea", "stall"),
+            cache_size: resolver.required("cache_size", "ea", "cache_size"),
+            dedup: resolver.flag("dedup", "ea", "dedup"),
+            islands: resolver.required("islands", "ea", "islands"),
+            migration_interval: resolver.required("migration_interval", "ea", "migration_interval"),
+            migrants: resolver.required("migrants", "ea", "migrants"),
+            topology: resolver.required("topology", "ea", "topology"),
+            diversity: resolver.required("diversity", "ea", "diversity"),
+            sharing_radius: resolver.required("sharing_radius", "ea", "sharing_radius"),
+            restart_diversity: resolver.parse("restart_diversity", "ea", "restart_diversity"),
+            optimiser: resolver.required("optimiser", "ea", "optimiser"),
+            temperature: resolver.required("temperature", "ea", "temperature"),
+            cooling: resolver.required("cooling", "ea", "cooling"),
+            cooling_rate: resolver.required("cooling_rate", "ea", "cooling_rate"),
+            learning_rate: resolver.required("learning_rate", "ea", "learning_rate"),
+            bound: resolver.flag("bound", "outputs", "bound"),
+            bound_states: resolver.required("bound_states", "outputs", "bound_states"),
+            method: resolver.required("method", "objectives", "method"),
+            objectives,
+            objective_weights,
+            objective_mode: resolver.required("objective_mode", "objectives", "objective_mode"),
+            thresholds: resolver.values("thresholds", "objectives", "thresholds"),
+            threshold_mode: resolver.required("threshold_mode", "objectives", "threshold_mode"),
+            threshold_penalty: resolver.required("threshold_penalty", "objectives", "threshold_penalty"),
+            utr: resolver.parse("utr", "objectives", "utr").unwrap_or_default(),
+            mfe_window,
+            native: resolver.parse("native", "objectives", "native"),
+            source: resolver.parse("source", "objectives", "source"),
+            harmony_mode: resolver.required("harmony_mode", "objectives", "harmony_mode"),
+            sites: resolver.values("sites", "constraints", "sites"),
+            site_mode: resolver.required("site_mode", "constraints", "site_mode"),
+            site_penalty: resolver.required("site_penalty", "constraints", "site_penalty"),
+            motifs: resolver.values("motifs", "constraints", "motifs"),
+            max_homopolymer: resolver.parse("max_homopolymer", "constraints", "max_homopolymer"),
+            required_motifs: resolver.values("required_motifs", "constraints", "required_motifs"),
+            motif_mode: resolver.required("motif_mode", "constraints", "motif_mode"),
+            motif_penalty: resolver.required("motif_penalty", "constraints", "motif_penalty"),
+            gc: resolver.values("gc", "constraints", "gc"),
+            gc_window: resolver.values("gc_window", "constraints", "gc_window"),
+            gc_mode: resolver.required("gc_mode", "constraints", "gc_mode"),
+            gc_penalty: resolver.required("gc_penalty", "constraints", "gc_penalty"),
+            repeat_length: resolver.parse("repeat_length", "constraints", "repeat_length"),
+            repeat_mode: resolver.required("repeat_mode", "constraints", "repeat_mode"),
+            repeat_penalty: resolver.required("repeat_penalty", "constraints", "repeat_penalty"),
+            template: resolver.parse("template", "template", "cds"),
+            lock: resolver.values("lock", "template", "lock"),
+            lock_mask: resolver.parse("lock_mask", "template", "mask"),
+            outfile: resolver.required("outfile", "outputs", "outfile"),
+            report: resolver.parse("report", "outputs", "report"),
+            trace: resolver.parse("trace", "outputs", "trace"),
+            summary: resolver.parse("summary", "outputs", "summary"),
+            top,
+            min_distance: resolver.required("min_distance", "outputs", "min_distance"),
+        }
+    }
+
+    pub fn host_names(&self) -> Vec<String> {
+        c![i.name.clone(), for i in &self.hosts]
+    }
+
+    pub fn weights(&self) -> Option<Vec<f64>> {
+        // Hosts without a weight count as 1 if any other host has one.
+        if self.hosts.iter().all(|i| i.weight.is_none()) {
+            None
+        } else {
+            Some(c![i.weight.unwrap_or(1.0), for i in &self.hosts])
+        }
+    }
+
+    // The resolved settings, in the same layout as the config file.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "protein": self.protein,
+            "table": self.table,
+            "memory_limit": self.memory_limit,
+            "hosts": c![json!({
+                "name": i.name,
+                "cds": i.cds,
+                "weight": i.weight,
+                "genes": i.genes,
+                "trna": i.trna,
+            }), for i in &self.hosts],
+            "ea": {
+                "crossovers": self.crossovers,
+                "mutations": self.mutations,
+                "generations": self.generations,
+                "gen_start": self.gen_start,
+                "seeds": self.seeds,
+                "seed_fraction": self.seed_fraction,
+                "random_seed": self.random_seed,
+                "stall": self.stall,
+                "cache_size": self.cache_size,
+                "dedup": self.dedup,
+                "islands": self.islands,
+                "migration_interval": self.migration_interval,
+                "migrants": self.migrants,
+                "topology": self.topology,
+                "diversity": self.diversity,
+                "sharing_radius": self.sharing_radius,
+                "restart_diversity": self.restart_diversity,
+                "optimiser": self.optimiser,
+                "temperature": self.temperature,
+                "cooling": self.cooling,
+                "cooling_rate": self.cooling_rate,
+                "learning_rate": self.learning_rate,
+            },
+            "objectives": {
+                "method": self.method,
+                "objectives": self.objectives,
+                "objective_weights": self.objective_weights,
+                "objective_mode": self.objective_mode,
+                "thresholds": self.thresholds,
+                "threshold_mode": self.threshold_mode,
+                "threshold_penalty": self.threshold_penalty,
+                "utr": self.utr,
+                "mfe_window": [self.mfe_window.0, self.mfe_window.1],
+                "native": self.native,
+                "source": self.source,
+                "harmony_mode": self.harmony_mode,
+            },
+            "constraints": {
+                "sites": self.sites,
+                "site_mode": self.site_mode,
+                "site_penalty": self.site_penalty,
+                "motifs": self.motifs,
+                "max_homopolymer": self.max_homopolymer,
+                "required_motifs": self.required_motifs,
+                "motif_mode": self.motif_mode,
+                "motif_penalty": self.motif_penalty,
+                "gc": self.gc,
+                "gc_window": self.gc_window,
+                "gc_mode": self.gc_mode,
+                "gc_penalty": self.gc_penalty,
+                "repeat_length": self.repeat_length,
+                "repeat_mode": self.repeat_mode,
+                "repeat_penalty": self.repeat_penalty,
+            },
+            "template": {
+                "cds": self.template,
+                "lock": self.lock,
+                "mask": self.lock_mask,
+            },
+            "outputs": {
+                "outfile": self.outfile,
+                "report": self.report,
+                "trace": self.trace,
+                "summary": self.summary,
+                "top": self.top,
+                "min_distance": self.min_distance,
+                "bound": self.bound,
+                "bound_states": self.bound_states,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::App;
+
+    // Resolves settings from command line arguments and an inline config file.
+    fn settings(args: &[&str], config: &str) -> Settings {
+        let yaml = load_yaml!("cli.yml");
+        let matches = App::from_yaml(yaml).get_matches_from([&["chimera-evolve", "-C", "inline.yml", "-o", "out.fasta"], args].concat());
+        let config = YamlLoader::load_from_str(config).unwrap().pop().unwrap_or(Yaml::Null);
+        Settings::from_config(&matches, config)
+    }
+
+    const CONFIG: &str = "
+protein: config.fasta
+table: 1
+hosts:
+  - name: first
+    cds: first.fasta
+    weight: 2
+    genes: [b0001, b0002]
+  - cds: hosts/second.fasta
+ea:
+  generations: 50
+  dedup: true
+objectives:
+  objectives: [ars, cai]
+";
+
+    #[test]
+    fn test_defaults() {
+        let settings = settings(&["protein.fasta", "host.fasta"], "");
+        assert_eq!(settings.protein, "protein.fasta");
+        assert_eq!(settings.table, 11);
+        assert_eq!(settings.generations, 1000);
+        assert!(!settings.dedup);
+        assert_eq!(settings.objectives, vec!["ars"]);
+        assert_eq!(settings.host_names(), vec!["host"]);
+        assert_eq!(settings.weights(), None);
+    }
+    #[test]
+    fn test_config_values() {
+        // The config file takes precedence over the defaults, at the top level and in sections.
+        let settings = settings(&[], CONFIG);
+        assert_eq!(settings.protein, "config.fasta");
+        assert_eq!(settings.table, 1);
+        assert_eq!(settings.generations, 50);
+        assert!(settings.dedup);
+        assert_eq!(settings.objectives, vec!["ars", "cai"]);
+        assert_eq!(settings.objective_weights, vec![1.0, 1.0]);
+        // Hosts are named after their CDS files unless given a name, and count as a weight of 1
+        // if another host has a weight.
+        assert_eq!(settings.host_names(), vec!["first", "second"]);
+        assert_eq!(settings.hosts[0].genes, vec!["b0001", "b0002"]);
+        assert_eq!(settings.weights(), Some(vec![2.0, 1.0]));
+    }
+    #[test]
+    fn test_command_line_values() {
+        // The command line takes precedence over the config file.
+        let args = ["protein.fasta", "a.fasta", "b.fasta", "-l", "11", "-g", "7", "--objectives", "tai"];
+        let settings = settings(&args, CONFIG);
+        assert_eq!(settings.protein, "protein.fasta");
+        assert_eq!(settings.table, 11);
+        assert_eq!(settings.generations, 7);
+        assert_eq!(settings.objectives, vec!["tai"]);
+        assert_eq!(settings.host_names(), vec!["a", "b"]);
+        assert_eq!(settings.weights(), None);
+        // A flag set in the config file is turned off on the command line by its no_ form.
+        assert!(settings.dedup);
+        assert!(!self::settings(&["--no_dedup"], CONFIG).dedup);
+        assert!(self::settings(&["--dedup"], "protein: p.fasta\nhosts: [{cds: h.fasta}]").dedup);
+        // Weights given on the command line replace those of the hosts in the config file.
+        let settings = self::settings(&["--weights", "3,4"], CONFIG);
+        assert_eq!(settings.host_names(), vec!["first", "second"]);
+        assert_eq!(settings.weights(), Some(vec![3.0, 4.0]));
+    }
+    #[test]
+    #[should_panic(expected = "Invalid value for weight: heavy")]
+    fn test_invalid_host_weight() {
+        settings(&[], "protein: p.fasta\nhosts: [{cds: h.fasta, weight: heavy}]");
+    }
+}
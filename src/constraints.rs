@@ -0,0 +1,1062 @@
+use regex::Regex;
+use std::collections::HashMap;
+
+use crate::preprocessing::encoding_to_nuc;
+
+// Recognition sequences of commonly used restriction enzymes.
+const ENZYMES: &[(&str, &str)] = &[
+    ("AarI", "CACCTGC"),
+    ("AvrII", "CCTAGG"),
+    ("BamHI", "GGATCC"),
+    ("BbsI", "GAAGAC"),
+    ("BglII", "AGATCT"),
+    ("BsaI", "GGTCTC"),
+    ("BsmBI", "CGTCTC"),
+    ("BtgZI", "GCGATG"),
+    ("EcoRI", "GAATTC"),
+    ("EcoRV", "GATATC"),
+    ("HindIII", "AAGCTT"),
+    ("KpnI", "GGTACC"),
+    ("MfeI", "CAATTG"),
+    ("NcoI", "CCATGG"),
+    ("NdeI", "CATATG"),
+    ("NheI", "GCTAGC"),
+    ("NotI", "GCGGCCGC"),
+    ("NsiI", "ATGCAT"),
+    ("PaqCI", "CACCTGC"),
+    ("PstI", "CTGCAG"),
+    ("SacI", "GAGCTC"),
+    ("SalI", "GTCGAC"),
+    ("SapI", "GCTCTTC"),
+    ("SmaI", "CCCGGG"),
+    ("SpeI", "ACTAGT"),
+    ("SphI", "GCATGC"),
+    ("XbaI", "TCTAGA"),
+    ("XhoI", "CTCGAG"),
+];
+
+// Regular expressions for motifs that are commonly kept out of designs.
+const MOTIFS: &[(&str, &str)] = &[
+    // Internal Shine-Dalgarno sequences can start translation part way through the CDS.
+    ("shine_dalgarno", "AGGAG|GGAGG"),
+    ("polya", "AATAAA|ATTAAA"),
+    ("splice_donor", "AGGT[AG]AGT"),
+    ("splice_acceptor", "[CT]{10}[ACGT]{0,3}CAG"),
+];
+
+pub fn reverse_complement(sequence: &str) -> String {
+    sequence
+        .chars()
+        .rev()
+        .map(|i| match i {
+            'A' => 'T',
+            'C' => 'G',
+            'G' => 'C',
+            'T' => 'A',
+            _ => 'N',
+        })
+        .collect::<String>()
+}
+
+/// A recognition sequence to keep out of designs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Site {
+    pub name: String,
+    pub sequence: String,
+}
+
+impl Site {
+    // Parses an enzyme name from the catalogue, or a recognition sequence.
+    pub fn parse(name: &str) -> Site {
+        if let Some((enzyme, sequence)) = ENZYMES
+            .iter()
+            .find(|(enzyme, _)| enzyme.eq_ignore_ascii_case(name))
+        {
+            return Site {
+                name: enzyme.to_string(),
+                sequence: sequence.to_string(),
+            };
+        }
+        let sequence = name.to_uppercase();
+        if sequence.is_empty() || !sequence.chars().all(|i| "ACGT".contains(i)) {
+            panic!("{} is neither a known enzyme nor a DNA recognition sequence", name);
+        }
+        Site {
+            name: sequence.clone(),
+            sequence,
+        }
+    }
+}
+
+/// An occurrence of a site in a nucleotide sequence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SiteHit {
+    pub name: String,
+    // Zero-based position of the first nucleotide of the hit on the forward strand.
+    pub position: usize,
+    pub reverse: bool,
+}
+
+/// A motif constraint on the forward strand of designs.
+#[derive(Debug, Clone)]
+pub enum Motif {
+    // A pattern that must not appear anywhere.
+    Forbidden { name: String, pattern: Regex },
+    // A sequence that must appear at a zero-based nucleotide position.
+    Required { sequence: String, position: usize },
+}
+
+impl Motif {
+    // Parses a motif name from the built-in list, or a regular expression.
+    pub fn forbidden(name: &str) -> Motif {
+        let pattern = MOTIFS
+            .iter()
+            .find(|(motif, _)| motif.eq_ignore_ascii_case(name))
+            .map_or(name, |(_, pattern)| pattern);
+        match Regex::new(pattern) {
+            // A pattern matching nothing at all would be found everywhere.
+            Ok(t) if t.is_match("") => panic!("Motif {} matches an empty sequence", name),
+            Ok(t) => Motif::Forbidden {
+                name: name.to_string(),
+                pattern: t,
+            },
+            Err(_) => panic!("{} is neither a known motif nor a valid regular expression", name),
+        }
+    }
+
+    // A motif matching runs of the same nucleotide longer than the given length.
+    pub fn homopolymer(max_length: usize) -> Motif {
+        let pattern = c![format!("{}{{{},}}", i, max_length + 1), for i in ["A", "C", "G", "T"]].join("|");
+        Motif::Forbidden {
+            name: format!("homopolymer>{}", max_length),
+            pattern: Regex::new(&pattern).unwrap(),
+        }
+    }
+
+    // Parses a required motif given as SEQUENCE@POSITION, with a one-based position.
+    pub fn required(motif: &str) -> Motif {
+        let parts = motif.split('@').collect::<Vec<&str>>();
+        let position = parts.get(1).and_then(|i| i.parse::<usize>().ok());
+        match (parts.len(), position) {
+            (2, Some(t)) if t > 0 && parts[0].chars().all(|i| "ACGT".contains(i)) => {
+                Motif::Required {
+                    sequence: parts[0].to_string(),
+                    position: t - 1,
+                }
+            }
+            _ => panic!("Required motifs must be given as SEQUENCE@POSITION, e.g. ATGAGC@1"),
+        }
+    }
+
+    // Whether the motif fits within a design of the given number of nucleotides.
+    pub fn fits(&self, length: usize) -> bool {
+        match self {
+            Motif::Forbidden { .. } => true,
+            Motif::Required { sequence, position } => position + sequence.len() <= length,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Motif::Forbidden { name, .. } => name.clone(),
+            Motif::Required { sequence, position } => format!("{}@{}", sequence, position + 1),
+        }
+    }
+}
+
+/// A place where a design breaks a constraint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub name: String,
+    // Zero-based position and length of the offending nucleotides.
+    pub position: usize,
+    pub length: usize,
+    // How far the design is from meeting the constraint here, used for penalties and repair.
+    pub severity: f64,
+}
+
+/// How candidates that break a constraint are treated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConstraintMode {
+    // Replace codons by synonymous alternatives until the constraint is met.
+    Repair,
+    // Subtract a penalty from the fitness for each violation, scaled by its severity.
+    Penalty,
+    // Give the candidate the lowest possible fitness.
+    Reject,
+}
+
+impl ConstraintMode {
+    pub fn parse(mode: &str) -> ConstraintMode {
+        match mode {
+            "repair" => ConstraintMode::Repair,
+            "penalty" => ConstraintMode::Penalty,
+            "reject" => ConstraintMode::Reject,
+            _ => panic!("Constraint mode given is not supported (repair, penalty, reject)"),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConstraintMode::Repair => "repair",
+            ConstraintMode::Penalty => "penalty",
+            ConstraintMode::Reject => "reject",
+        }
+    }
+}
+
+/// Bounds on the proportion of G and C in a design.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GcBounds {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl GcBounds {
+    // Parses bounds given as MIN,MAX.
+    pub fn parse(values: &[String]) -> GcBounds {
+        let bounds = c![i.parse::<f64>().ok(), for i in values];
+        match bounds.as_slice() {
+            [Some(min), Some(max)] if min <= max => GcBounds { min: *min, max: *max },
+            _ => panic!("GC bounds must be given as MIN,MAX, e.g. 0.25,0.65"),
+        }
+    }
+}
+
+/// Bounds on the proportion of G and C in every window of a design.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GcWindow {
+    pub size: usize,
+    pub bounds: GcBounds,
+}
+
+impl GcWindow {
+    // Parses window bounds given as SIZE,MIN,MAX.
+    pub fn parse(values: &[String]) -> GcWindow {
+        match values.first().map(|i| i.parse::<usize>()) {
+            Some(Ok(size)) if values.len() == 3 && size > 0 => GcWindow {
+                size,
+                bounds: GcBounds::parse(&values[1..]),
+            },
+            _ => panic!("GC window bounds must be given as SIZE,MIN,MAX, e.g. 50,0.3,0.7"),
+        }
+    }
+}
+
+pub fn gc_content(nucleotides: &str) -> f64 {
+    let gc = nucleotides.chars().filter(|i| *i == 'G' || *i == 'C').count();
+    gc as f64 / nucleotides.len() as f64
+}
+
+fn gc_violation(name: &str, position: usize, length: usize, gc: usize, bounds: &GcBounds) -> Option<Violation> {
+    // The severity is the number of G or C nucleotides that would have to change.
+    let low = bounds.min * length as f64 - gc as f64;
+    let high = gc as f64 - bounds.max * length as f64;
+    let severity = low.max(high);
+    if severity > 0.0 {
+        Some(Violation {
+            name: name.to_string(),
+            position,
+            length,
+            severity: severity.ceil(),
+        })
+    } else {
+        None
+    }
+}
+
+/// Kinds of repeat that make synthesis and assembly fail.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RepeatKind {
+    Direct,
+    Inverted,
+    Palindrome,
+}
+
+impl RepeatKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RepeatKind::Direct => "direct_repeat",
+            RepeatKind::Inverted => "inverted_repeat",
+            RepeatKind::Palindrome => "palindrome",
+        }
+    }
+}
+
+/// A repeated stretch of a design. For palindromes, both copies are the same stretch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Repeat {
+    pub kind: RepeatKind,
+    // Zero-based positions of the two copies, and their length.
+    pub first: usize,
+    pub second: usize,
+    pub length: usize,
+}
+
+pub fn find_repeats(nucleotides: &str, min_length: usize) -> Vec<Repeat> {
+    // Finds maximal direct and inverted repeats, and palindromes, of at least min_length nucleotides.
+    let sequence = nucleotides.as_bytes();
+    let reverse = reverse_complement(nucleotides);
+    let reverse = reverse.as_bytes();
+    let n = sequence.len();
+    let mut repeats: Vec<Repeat> = vec![];
+    if min_length == 0 || n < min_length {
+        return repeats;
+    }
+
+    // Index the positions of every k-mer in a hash table, chaining the positions in each slot in
+    // order. Different k-mers may share a slot, so k-mers are compared before being paired.
+    let hashes = kmer_hashes(sequence, min_length);
+    let shift = 64 - (2 * hashes.len()).next_power_of_two().trailing_zeros();
+    let slot = |hash: u64| (hash.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> shift) as usize;
+    let mut heads = vec![usize::MAX; 1 << (64 - shift)];
+    let mut next = vec![usize::MAX; hashes.len()];
+    for i in (0..hashes.len()).rev() {
+        next[i] = heads[slot(hashes[i])];
+        heads[slot(hashes[i])] = i;
+    }
+    let end = |i: usize| Some(i).filter(|j| *j != usize::MAX);
+    let chain = |i: usize| std::iter::successors(end(i), |j| end(next[*j]));
+    let same = |i: usize, j: usize| hashes[i] == hashes[j] && sequence[i..i + min_length] == sequence[j..j + min_length];
+
+    // Direct repeats extend from (i, j) to (i + 1, j + 1), so report those that do not extend an earlier pair.
+    let extends = |i: usize, j: usize| i > 0 && sequence[i - 1] == sequence[j - 1];
+    for i in 0..hashes.len() {
+        for j in chain(next[i]) {
+            if !same(i, j) || extends(i, j) {
+                continue;
+            }
+            let mut length = min_length;
+            while j + length < n && sequence[i + length] == sequence[j + length] {
+                length += 1;
+            }
+            repeats.push(Repeat {
+                kind: RepeatKind::Direct,
+                first: i,
+                second: j,
+                length,
+            });
+        }
+    }
+
+    // Inverted repeats: the k-mer at i is the reverse complement of the one at j, without overlapping.
+    // The reverse complement of the k-mer at j starts at n - j - k in the reversed sequence.
+    let reverse_hashes = kmer_hashes(reverse, min_length);
+    for i in 0..=(n - min_length) {
+        let kmer = &reverse[n - i - min_length..n - i];
+        for j in chain(heads[slot(reverse_hashes[n - i - min_length])]) {
+            if j < i + min_length || sequence[j..j + min_length] != *kmer {
+                continue;
+            }
+            // Report pairs that do not extend (i - 1, j + 1).
+            if i > 0 && j + min_length < n && sequence[i - 1] == reverse[n - j - min_length - 1] {
+                continue;
+            }
+            // Grow the first copy rightwards and the second copy leftwards while they stay apart.
+            let mut extra = 0;
+            while extra < j
+                && i + min_length + extra < j - extra - 1
+                && sequence[i + min_length + extra] == reverse[n - j + extra]
+            {
+                extra += 1;
+            }
+            let length = min_length + extra;
+            repeats.push(Repeat {
+                kind: RepeatKind::Inverted,
+                first: i,
+                second: j - extra,
+                length,
+            });
+        }
+    }
+
+    repeats.extend(find_palindromes(sequence, reverse, min_length));
+    repeats.sort_by_key(|i| (i.second, i.first));
+    repeats
+}
+
+fn find_palindromes(sequence: &[u8], reverse: &[u8], min_length: usize) -> Vec<Repeat> {
+    // Palindromes: stretches equal to their own reverse complement, grown out from each centre.
+    let n = sequence.len();
+    let mut repeats: Vec<Repeat> = vec![];
+    for centre in 1..n {
+        let mut radius = 0;
+        while radius < centre
+            && centre + radius < n
+            && sequence[centre - radius - 1] == reverse[n - centre - radius - 1]
+        {
+            radius += 1;
+        }
+        if 2 * radius >= min_length {
+            repeats.push(Repeat {
+                kind: RepeatKind::Palindrome,
+                first: centre - radius,
+                second: centre - radius,
+                length: 2 * radius,
+            });
+        }
+    }
+    repeats
+}
+
+// Whether a copy of a repeat, or a nucleotide either side of it that stops it growing, lies between
+// start and end (exclusive). Repeats that do not touch a changed stretch in this way are unchanged.
+fn touches(first: usize, length: usize, start: usize, end: usize) -> bool {
+    first <= end && first + length >= start
+}
+
+// Rolling hashes of every k-mer of a sequence, so that k-mers can be compared in one step.
+fn kmer_hashes(sequence: &[u8], k: usize) -> Vec<u64> {
+    const BASE: u64 = 0x100_0000_01b3;
+    let top = (1..k).fold(1u64, |i, _j| i.wrapping_mul(BASE));
+    let mut hash = sequence[..k].iter().fold(0u64, |i, j| i.wrapping_mul(BASE).wrapping_add(*j as u64));
+    let mut hashes = vec![hash];
+    for i in k..sequence.len() {
+        hash = hash
+            .wrapping_sub((sequence[i - k] as u64).wrapping_mul(top))
+            .wrapping_mul(BASE)
+            .wrapping_add(sequence[i] as u64);
+        hashes.push(hash);
+    }
+    hashes
+}
+
+fn find_repeats_near(nucleotides: &str, min_length: usize, start: usize, end: usize) -> Vec<Repeat> {
+    // Finds the repeats find_repeats would report that touch the nucleotides from start to end,
+    // without searching for every repeat. Each such repeat has a k-mer starting within min_length
+    // of the stretch, from which it is grown out to the same extent as by find_repeats.
+    let sequence = nucleotides.as_bytes();
+    let n = sequence.len();
+    let mut repeats: Vec<Repeat> = vec![];
+    if min_length == 0 || n < min_length {
+        return repeats;
+    }
+    let k = min_length;
+    let complement = |i: u8| match i {
+        b'A' => b'T',
+        b'T' => b'A',
+        b'G' => b'C',
+        b'C' => b'G',
+        _ => i,
+    };
+    // The k-mer at q reversed and complemented starts at n - q - k in the reverse complement.
+    let reverse = reverse_complement(nucleotides);
+    let reverse = reverse.as_bytes();
+    let (forward_hashes, reverse_hashes) = (kmer_hashes(sequence, k), kmer_hashes(reverse, k));
+    let is_direct = |i: usize, j: usize| {
+        forward_hashes[i] == forward_hashes[j] && sequence[i..i + k] == sequence[j..j + k]
+    };
+    let is_reverse = |i: usize, j: usize| {
+        forward_hashes[i] == reverse_hashes[n - j - k] && sequence[i..i + k] == reverse[n - j - k..n - j]
+    };
+    let near = |i: &Repeat| touches(i.first, i.length, start, end) || touches(i.second, i.length, start, end);
+
+    // Mark the hashes of the k-mers near the stretch, to pass over most of the others at a glance.
+    let window = start.saturating_sub(k)..=end.min(n - k);
+    let mark = |hash: u64| {
+        let bit = (hash.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 54) as usize;
+        (bit / 64, 1u64 << (bit % 64))
+    };
+    let mut marks = [0u64; 16];
+    for p in window.clone() {
+        let (i, bit) = mark(forward_hashes[p]);
+        marks[i] |= bit;
+    }
+    let marked = |hash: u64| {
+        let (i, bit) = mark(hash);
+        marks[i] & bit != 0
+    };
+
+    for q in 0..=(n - k) {
+        let (direct, inverted) = (marked(forward_hashes[q]), marked(reverse_hashes[n - q - k]));
+        if !direct && !inverted {
+            continue;
+        }
+        for p in window.clone() {
+            // Direct repeats: walk back along the diagonal to where find_repeats starts it.
+            if direct && q != p && is_direct(p, q) {
+                let (mut i, mut j) = (p.min(q), p.max(q));
+                while i > 0 && sequence[i - 1] == sequence[j - 1] {
+                    i -= 1;
+                    j -= 1;
+                }
+                let mut length = k;
+                while j + length < n && sequence[i + length] == sequence[j + length] {
+                    length += 1;
+                }
+                repeats.push(Repeat {
+                    kind: RepeatKind::Direct,
+                    first: i,
+                    second: j,
+                    length,
+                });
+            }
+            // Inverted repeats: walk outwards along the anti-diagonal, then grow inwards.
+            if inverted && is_reverse(p, q) {
+                let (mut i, mut j) = (p.min(q), p.max(q));
+                while i > 0 && j + k < n && sequence[i - 1] == complement(sequence[j + k]) {
+                    i -= 1;
+                    j += 1;
+                }
+                if j < i + k {
+                    continue;
+                }
+                let mut extra = 0;
+                while extra < j
+                    && i + k + extra < j - extra - 1
+                    && sequence[i + k + extra] == complement(sequence[j - extra - 1])
+                {
+                    extra += 1;
+                }
+                repeats.push(Repeat {
+                    kind: RepeatKind::Inverted,
+                    first: i,
+                    second: j - extra,
+                    length: k + extra,
+                });
+            }
+        }
+    }
+
+    // Palindromes are short, so finding them all is cheap.
+    repeats.extend(find_palindromes(sequence, reverse, k));
+    repeats.retain(|i| near(i));
+    repeats.sort_by_key(|i| (i.second, i.first, i.length, i.kind as usize));
+    repeats.dedup();
+    repeats
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Group {
+    Sites,
+    Motifs,
+    Gc,
+    Repeats,
+}
+
+const GROUPS: [Group; 4] = [Group::Sites, Group::Motifs, Group::Gc, Group::Repeats];
+
+/// Constraints on the nucleotide sequence of designs.
+pub struct Constraints<'a> {
+    pub sites: Vec<Site>,
+    pub site_mode: ConstraintMode,
+    pub site_penalty: f64,
+    pub motifs: Vec<Motif>,
+    pub motif_mode: ConstraintMode,
+    pub motif_penalty: f64,
+    pub gc: Option<GcBounds>,
+    pub gc_window: Option<GcWindow>,
+    pub gc_mode: ConstraintMode,
+    pub gc_penalty: f64,
+    // Shortest repeat to keep out of designs.
+    pub repeat_length: Option<usize>,
+    pub repeat_mode: ConstraintMode,
+    pub repeat_penalty: f64,
+    singular_codon_map: &'a HashMap<&'a str, &'a str>,
+    alternatives: &'a HashMap<&'a str, Vec<&'a str>>,
+}
+
+impl<'a> Constraints<'a> {
+    pub fn new(
+        singular_codon_map: &'a HashMap<&'a str, &'a str>,
+        alternatives: &'a HashMap<&'a str, Vec<&'a str>>,
+    ) -> Constraints<'a> {
+        Constraints {
+            sites: vec![],
+            site_mode: ConstraintMode::Repair,
+            site_penalty: 1.0,
+            motifs: vec![],
+            motif_mode: ConstraintMode::Repair,
+            motif_penalty: 1.0,
+            gc: None,
+            gc_window: None,
+            gc_mode: ConstraintMode::Repair,
+            gc_penalty: 1.0,
+            repeat_length: None,
+            repeat_mode: ConstraintMode::Repair,
+            repeat_penalty: 1.0,
+            singular_codon_map,
+            alternatives,
+        }
+    }
+
+    // Whether a group of constraints is in use, how it is enforced, and its penalty.
+    fn group(&self, group: Group) -> (bool, ConstraintMode, f64) {
+        match group {
+            Group::Sites => (!self.sites.is_empty(), self.site_mode, self.site_penalty),
+            Group::Motifs => (!self.motifs.is_empty(), self.motif_mode, self.motif_penalty),
+            Group::Gc => (
+                self.gc.is_some() || self.gc_window.is_some(),
+                self.gc_mode,
+                self.gc_penalty,
+            ),
+            Group::Repeats => (
+                self.repeat_length.is_some(),
+                self.repeat_mode,
+                self.repeat_penalty,
+            ),
+        }
+    }
+
+    fn violations(&self, group: Group, nucleotides: &str) -> Vec<Violation> {
+        match group {
+            Group::Sites => self.site_violations(nucleotides),
+            Group::Motifs => self.find_motifs(nucleotides),
+            Group::Gc => self.find_gc(nucleotides),
+            Group::Repeats => self.repeat_violations(nucleotides),
+        }
+    }
+
+    // Sums the severity of the violations of a group that could change when the nucleotides from
+    // start to end (exclusive) change. The rest of the design's violations stay as they are.
+    fn local_severity(&self, group: Group, nucleotides: &str, start: usize, end: usize) -> f64 {
+        let sequence = nucleotides.as_bytes();
+        let n = sequence.len();
+        match group {
+            // Sites overlapping the stretch.
+            Group::Sites => {
+                let mut hits = 0;
+                for site in &self.sites {
+                    let reverse = reverse_complement(&site.sequence);
+                    let length = site.sequence.len();
+                    for position in start.saturating_sub(length - 1)..end.min((n + 1).saturating_sub(length)) {
+                        let window = &nucleotides[position..position + length];
+                        hits += (window == site.sequence) as usize;
+                        hits += (reverse != site.sequence && window == reverse) as usize;
+                    }
+                }
+                hits as f64
+            }
+            // A match of a regular expression can run any distance, so they are all found again,
+            // but only the nucleotides of required motifs within the stretch are checked.
+            Group::Motifs => self
+                .motifs
+                .iter()
+                .map(|motif| match motif {
+                    Motif::Forbidden { pattern, .. } => pattern.find_iter(nucleotides).count(),
+                    Motif::Required { sequence: required, position } => required
+                        .bytes()
+                        .enumerate()
+                        .map(|(offset, expected)| (position + offset, expected))
+                        .filter(|(i, expected)| *i >= start && *i < end && sequence.get(*i) != Some(expected))
+                        .count(),
+                })
+                .sum::<usize>() as f64,
+            // The whole design, and the windows overlapping the stretch.
+            Group::Gc => {
+                let count = |i: &[u8]| i.iter().filter(|j| **j == b'G' || **j == b'C').count();
+                let mut severity = 0.0;
+                if let Some(t) = &self.gc {
+                    severity += gc_violation("gc", 0, n, count(sequence), t).map_or(0.0, |i| i.severity);
+                }
+                if let Some(t) = &self.gc_window {
+                    let size = t.size.min(n);
+                    for position in start.saturating_sub(size - 1)..end.min(n + 1 - size) {
+                        let gc = count(&sequence[position..position + size]);
+                        severity += gc_violation("gc_window", position, size, gc, &t.bounds).map_or(0.0, |i| i.severity);
+                    }
+                }
+                severity
+            }
+            Group::Repeats => match self.repeat_length {
+                Some(t) => find_repeats_near(nucleotides, t, start, end).len() as f64,
+                None => 0.0,
+            },
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        GROUPS.iter().all(|i| !self.group(*i).0)
+    }
+
+    pub fn nucleotides(&self, encoded: &str) -> String {
+        encoding_to_nuc(encoded, self.singular_codon_map)
+    }
+
+    // Finds every occurrence of every site in a nucleotide sequence, on both strands.
+    pub fn find_sites(&self, nucleotides: &str) -> Vec<SiteHit> {
+        let mut hits: Vec<SiteHit> = vec![];
+        for site in &self.sites {
+            let reverse = reverse_complement(&site.sequence);
+            for (sequence, is_reverse) in [(&site.sequence, false), (&reverse, true)].iter() {
+                // Palindromic sites are only reported once.
+                if *is_reverse && reverse == site.sequence {
+                    continue;
+                }
+                let length = sequence.len();
+                for position in 0..(nucleotides.len() + 1).saturating_sub(length) {
+                    if &nucleotides[position..position + length] == sequence.as_str() {
+                        hits.push(SiteHit {
+                            name: site.name.clone(),
+                            position,
+                            reverse: *is_reverse,
+                        });
+                    }
+                }
+            }
+        }
+        hits.sort_by_key(|i| i.position);
+        hits
+    }
+
+    fn site_violations(&self, nucleotides: &str) -> Vec<Violation> {
+        c![Violation {
+            name: i.name.clone(),
+            position: i.position,
+            length: self.sites.iter().find(|j| j.name == i.name).unwrap().sequence.len(),
+            severity: 1.0,
+        }, for i in self.find_sites(nucleotides)]
+    }
+
+    // Finds forbidden motifs, and every mismatched nucleotide of required motifs.
+    pub fn find_motifs(&self, nucleotides: &str) -> Vec<Violation> {
+        let mut violations: Vec<Violation> = vec![];
+        for motif in &self.motifs {
+            match motif {
+                Motif::Forbidden { name, pattern } => {
+                    violations.extend(pattern.find_iter(nucleotides).map(|i| Violation {
+                        name: name.clone(),
+                        position: i.start(),
+                        length: i.end() - i.start(),
+                        severity: 1.0,
+                    }));
+                }
+                Motif::Required { sequence, position } => {
+                    for (offset, expected) in sequence.chars().enumerate() {
+                        if nucleotides.as_bytes().get(position + offset) != Some(&(expected as u8)) {
+                            violations.push(Violation {
+                                name: motif.name(),
+                                position: position + offset,
+                                length: 1,
+                                severity: 1.0,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        violations.sort_by_key(|i| i.position);
+        violations
+    }
+
+    // Finds where the GC content of the whole design, or of any window of it, is out of bounds.
+    pub fn find_gc(&self, nucleotides: &str) -> Vec<Violation> {
+        let mut violations: Vec<Violation> = vec![];
+        let is_gc = c![(i == b'G' || i == b'C') as usize, for i in nucleotides.bytes()];
+        if let Some(t) = &self.gc {
+            let gc = is_gc.iter().sum::<usize>();
+            violations.extend(gc_violation("gc", 0, is_gc.len(), gc, t));
+        }
+        if let Some(t) = &self.gc_window {
+            // Slide the window along one nucleotide at a time, keeping a running count.
+            let size = t.size.min(is_gc.len());
+            let mut gc = is_gc[..size].iter().sum::<usize>();
+            for position in 0..=(is_gc.len() - size) {
+                if position > 0 {
+                    gc = gc + is_gc[position + size - 1] - is_gc[position - 1];
+                }
+                violations.extend(gc_violation("gc_window", position, size, gc, &t.bounds));
+            }
+        }
+        violations
+    }
+
+    pub fn find_repeats(&self, nucleotides: &str) -> Vec<Repeat> {
+        match self.repeat_length {
+            Some(t) => find_repeats(nucleotides, t),
+            None => vec![],
+        }
+    }
+
+    fn repeat_violations(&self, nucleotides: &str) -> Vec<Violation> {
+        // Repairs are made to the second copy of each repeat.
+        c![Violation {
+            name: i.kind.as_str().to_string(),
+            position: i.second,
+            length: i.length,
+            severity: 1.0,
+        }, for i in self.find_repeats(nucleotides)]
+    }
+
+    pub fn count_sites(&self, encoded: &str) -> usize {
+        if self.sites.is_empty() {
+            return 0;
+        }
+        self.find_sites(&self.nucleotides(encoded)).len()
+    }
+
+    pub fn count_motifs(&self, encoded: &str) -> usize {
+        if self.motifs.is_empty() {
+            return 0;
+        }
+        self.find_motifs(&self.nucleotides(encoded)).len()
+    }
+
+    fn repairable_violations(&self, nucleotides: &str) -> Vec<Violation> {
+        let mut violations: Vec<Violation> = vec![];
+        for group in GROUPS.iter() {
+            let (active, mode, _) = self.group(*group);
+            if active && mode == ConstraintMode::Repair {
+                violations.extend(self.violations(*group, nucleotides));
+            }
+        }
+        violations.sort_by_key(|i| i.position);
+        violations
+    }
+
+    pub fn needs_repair(&self) -> bool {
+        GROUPS.iter().any(|i| {
+            let (active, mode, _) = self.group(*i);
+            active && mode == ConstraintMode::Repair
+        })
+    }
+
+    // Removes violations of constraints in repair mode by swapping the codons they overlap for
+    // synonymous alternatives. Locked codons are not changed, and any violations that cannot be
+    // removed this way are left in place and returned with the repaired sequence.
+    pub fn repair(&self, encoded: &str, locked: &[bool]) -> (String, Vec<Violation>) {
+        let groups = c![*i, for i in GROUPS.iter(), if self.group(*i).0 && self.group(*i).1 == ConstraintMode::Repair];
+        let mut codons = encoded.chars().collect::<Vec<char>>();
+        let mut nucleotides = self.nucleotides(encoded).into_bytes();
+        let mut violations = self.repairable_violations(std::str::from_utf8(&nucleotides).unwrap());
+        let mut unrepairable: Vec<Violation> = vec![];
+        // Where to start looking for a change, so long violations are not rescanned from the start.
+        let mut hint = 0;
+
+        while let Some(violation) = violations
+            .iter()
+            .find(|i| !unrepairable.contains(i))
+            .cloned()
+        {
+            let first = violation.position / 3;
+            let last = ((violation.position + violation.length).saturating_sub(1) / 3).min(codons.len().saturating_sub(1));
+            // Violations that are empty or lie past the end of the design overlap no codons.
+            if violation.length == 0 || first > last {
+                unrepairable.push(violation);
+                continue;
+            }
+            let span = last - first + 1;
+            let offset = if hint >= first && hint <= last { hint - first } else { 0 };
+
+            // Take the first synonymous change that reduces the total severity of violations. Only
+            // the violations near the codon can change, so only those are checked.
+            let mut repaired = false;
+            'codons: for k in 0..span {
+                let idx = first + (offset + k) % span;
+                if locked.get(idx) == Some(&true) {
+                    continue;
+                }
+                let (start, end) = (idx * 3, idx * 3 + 3);
+                let severity = |i: &[u8]| {
+                    let trial = std::str::from_utf8(i).unwrap();
+                    groups.iter().map(|j| self.local_severity(*j, trial, start, end)).sum::<f64>()
+                };
+                let before = severity(&nucleotides);
+                let codon = nucleotides[start..end].to_vec();
+                for alternative in self.alternatives.get::<str>(&codons[idx].to_string()).unwrap() {
+                    nucleotides[start..end].copy_from_slice(self.singular_codon_map.get(alternative).unwrap().as_bytes());
+                    if severity(&nucleotides) < before {
+                        codons[idx] = alternative.chars().next().unwrap();
+                        violations = self.repairable_violations(std::str::from_utf8(&nucleotides).unwrap());
+                        hint = idx + 1;
+                        repaired = true;
+                        break 'codons;
+                    }
+                }
+                nucleotides[start..end].copy_from_slice(&codon);
+            }
+            if !repaired {
+                unrepairable.push(violation);
+            }
+        }
+        (codons.into_iter().collect::<String>(), violations)
+    }
+
+    // Calculates the fitness of a candidate after constraints, given its fitness before them, and
+    // the violations left by repair if it was made, so that they are not found again.
+    pub fn adjust_fitness(&self, encoded: &str, fitness: f64, repaired: Option<&[Violation]>) -> f64 {
+        if self.is_empty() {
+            return fitness;
+        }
+        let nucleotides = self.nucleotides(encoded);
+        let mut adjusted = fitness;
+        for group in GROUPS.iter() {
+            let (active, mode, penalty) = self.group(*group);
+            if !active {
+                continue;
+            }
+            let severity: f64 = match (mode, repaired) {
+                (ConstraintMode::Repair, Some(t)) => t.iter().map(|i| i.severity).sum(),
+                _ => self.violations(*group, &nucleotides).iter().map(|i| i.severity).sum(),
+            };
+            if severity == 0.0 {
+                continue;
+            }
+            match mode {
+                ConstraintMode::Penalty => adjusted -= penalty * severity,
+                // Violations that could not be repaired are rejected.
+                ConstraintMode::Reject | ConstraintMode::Repair => return f64::NEG_INFINITY,
+            }
+        }
+        adjusted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translation_tables;
+
+    #[test]
+    fn test_reverse_complement() {
+        assert_eq!(reverse_complement("GGTCTC"), "GAGACC");
+    }
+
+    #[test]
+    fn test_site_parse() {
+        assert_eq!(Site::parse("bsai").sequence, "GGTCTC");
+        assert_eq!(Site::parse("acgtac").name, "ACGTAC");
+    }
+
+    #[test]
+    fn test_find_sites_both_strands() {
+        let (_, singular_codon_map, _, _, _, alternatives) =
+            translation_tables::generate_maps(translation_tables::tt11());
+        let mut constraints = Constraints::new(&singular_codon_map, &alternatives);
+        constraints.sites = vec![Site::parse("BsaI"), Site::parse("EcoRI")];
+        let hits = constraints.find_sites("AGGTCTCAAGAGACCAGAATTC");
+        let found = c![(i.name.as_str(), i.position, i.reverse), for i in &hits];
+        assert_eq!(
+            found,
+            vec![("BsaI", 1, false), ("BsaI", 9, true), ("EcoRI", 16, false)]
+        );
+    }
+
+    #[test]
+    fn test_repair() {
+        let (codon_singular_map, singular_codon_map, _, _, _, alternatives) =
+            translation_tables::generate_maps(translation_tables::tt11());
+        let mut constraints = Constraints::new(&singular_codon_map, &alternatives);
+        constraints.sites = vec![Site::parse("EcoRI")];
+        // ATG GAA TTC GGT contains GAATTC.
+        let encoded = c![*codon_singular_map.get(i).unwrap(), for i in ["ATG", "GAA", "TTC", "GGT"]].concat();
+        assert_eq!(constraints.count_sites(&encoded), 1);
+        let (repaired, violations) = constraints.repair(&encoded, &[]);
+        assert_eq!(constraints.count_sites(&repaired), 0);
+        assert!(violations.is_empty());
+        assert_ne!(repaired, encoded);
+        // With both codons the site overlaps locked, it cannot be repaired.
+        let (repaired, violations) = constraints.repair(&encoded, &[false, true, true, false]);
+        assert_eq!(repaired, encoded);
+        assert_eq!(violations.len(), 1);
+    }
+
+    #[test]
+    fn test_find_motifs() {
+        let (_, singular_codon_map, _, _, _, alternatives) =
+            translation_tables::generate_maps(translation_tables::tt11());
+        let mut constraints = Constraints::new(&singular_codon_map, &alternatives);
+        constraints.motifs = vec![
+            Motif::forbidden("polya"),
+            Motif::homopolymer(4),
+            Motif::required("ATGC@1"),
+        ];
+        let violations = constraints.find_motifs("ATGAATAAACCCCCG");
+        let found = c![(i.name.as_str(), i.position, i.length), for i in &violations];
+        assert_eq!(
+            found,
+            vec![("polya", 3, 6), ("ATGC@1", 3, 1), ("homopolymer>4", 9, 5)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Motif C* matches an empty sequence")]
+    fn test_empty_motif() {
+        Motif::forbidden("C*");
+    }
+
+    #[test]
+    fn test_repair_out_of_range() {
+        let (codon_singular_map, singular_codon_map, _, _, _, alternatives) =
+            translation_tables::generate_maps(translation_tables::tt11());
+        let mut constraints = Constraints::new(&singular_codon_map, &alternatives);
+        // A required motif past the end of the design cannot be repaired, but leaves it unchanged.
+        constraints.motifs = vec![Motif::required("ATG@100")];
+        assert!(!constraints.motifs[0].fits(12));
+        let encoded = c![*codon_singular_map.get(i).unwrap(), for i in ["ATG", "TTA", "CTT", "GGT"]].concat();
+        assert_eq!(constraints.repair(&encoded, &[]).0, encoded);
+    }
+
+    #[test]
+    fn test_repair_required_motif() {
+        let (codon_singular_map, singular_codon_map, _, _, _, alternatives) =
+            translation_tables::generate_maps(translation_tables::tt11());
+        let mut constraints = Constraints::new(&singular_codon_map, &alternatives);
+        constraints.motifs = vec![Motif::required("CTGCTG@4")];
+        let encoded = c![*codon_singular_map.get(i).unwrap(), for i in ["ATG", "TTA", "CTT", "GGT"]].concat();
+        let repaired = constraints.repair(&encoded, &[]).0;
+        assert_eq!(constraints.nucleotides(&repaired), "ATGCTGCTGGGT");
+    }
+
+    #[test]
+    fn test_find_gc() {
+        let (codon_singular_map, singular_codon_map, _, _, _, alternatives) =
+            translation_tables::generate_maps(translation_tables::tt11());
+        let mut constraints = Constraints::new(&singular_codon_map, &alternatives);
+        constraints.gc = Some(GcBounds { min: 0.4, max: 0.6 });
+        constraints.gc_window = Some(GcWindow {
+            size: 6,
+            bounds: GcBounds { min: 0.0, max: 0.5 },
+        });
+        // 3 of 12 nucleotides are G or C, and only the first window has more than 3.
+        let violations = constraints.find_gc("GCGAAATTTAAA");
+        let found = c![(i.name.as_str(), i.position, i.length, i.severity), for i in &violations];
+        assert_eq!(found, vec![("gc", 0, 12, 2.0)]);
+
+        let violations = constraints.find_gc("GCGCAATTTGAA");
+        let found = c![(i.name.as_str(), i.position), for i in &violations];
+        assert_eq!(found, vec![("gc_window", 0)]);
+
+        // Repair raises the GC content with synonymous changes: AAA -> AAG.
+        let encoded = c![*codon_singular_map.get(i).unwrap(), for i in ["AAA", "AAA", "AAA", "AAA"]].concat();
+        constraints.gc = Some(GcBounds { min: 0.25, max: 0.6 });
+        constraints.gc_window = None;
+        let repaired = constraints.repair(&encoded, &[]).0;
+        assert!(constraints.find_gc(&constraints.nucleotides(&repaired)).is_empty());
+    }
+
+    #[test]
+    fn test_find_repeats() {
+        // ACGATCGT is its own reverse complement, and AAACCC / GGGTTT are inverted repeats.
+        let repeats = find_repeats("AAACCCTACGATCGTTGGGTTT", 6);
+        let found = c![(i.kind, i.first, i.second, i.length), for i in &repeats];
+        assert_eq!(
+            found,
+            vec![
+                (RepeatKind::Palindrome, 7, 7, 8),
+                (RepeatKind::Inverted, 0, 16, 6)
+            ]
+        );
+
+        let repeats = find_repeats("GATTACAGGGGATTACAG", 7);
+        let found = c![(i.kind, i.first, i.second, i.length), for i in &repeats];
+        assert_eq!(found, vec![(RepeatKind::Direct, 0, 10, 8)]);
+    }
+
+    #[test]
+    fn test_find_repeats_near() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(1);
+        for _i in 0..200 {
+            let n = rng.gen_range(10, 60);
+            let nucleotides = c![b"ACGT"[rng.gen_range(0, 2 + n % 3)] as char, for _j in 0..n].into_iter().collect::<String>();
+            let start = rng.gen_range(0, n - 3);
+            let end = start + 3;
+            let mut expected = find_repeats(&nucleotides, 4);
+            expected.retain(|i| touches(i.first, i.length, start, end) || touches(i.second, i.length, start, end));
+            expected.sort_by_key(|i| (i.second, i.first, i.length, i.kind as usize));
+            assert_eq!(find_repeats_near(&nucleotides, 4, start, end), expected, "{} {}", nucleotides, start);
+        }
+    }
+}
@@ -1,101 +1,167 @@
+use rand::rngs::StdRng;
 use rand::seq::IteratorRandom;
-use rand::{thread_rng, Rng};
-use std::collections::HashMap;
+use rand::{Rng, SeedableRng};
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
 use std::f64;
-use suffix::SuffixTable;
+use crate::cache::Cache;
+use crate::constraints::Constraints;
+use crate::matching::{MatchIndex, Matches, MatchingStatistics};
+use crate::objectives::Objectives;
+use crate::optimisers::{Optimiser, Problem};
+use crate::trace::GenerationStats;
 
+use crate::rayon::iter::IndexedParallelIterator;
 use crate::rayon::iter::IntoParallelRefMutIterator;
 use crate::rayon::iter::ParallelIterator;
 
+// Reference implementation of ARS from a whole string's match lengths. Candidates are scored from
+// matching statistics updated in place, and the tests check the two agree.
 #[allow(dead_code)]
-fn calculate_ars(string: &str, suffix_table: &suffix::SuffixTable) -> f64 {
-    // For each position in the string.
-    let ars: f64 = string
-        .chars()
-        .enumerate()
-        // Get the per-position score for each substring
-        .map(|(idx, _)| {
-            let substring = &string[idx..];
-            let substring_len = substring.len();
-            let mut counter = 0.0;
-            for s_idx in 0..substring_len {
-                let subsubstring = &substring[..=s_idx];
-                if suffix_table.contains(subsubstring) {
-                    counter += 1.0;
-                } else {
-                    break;
-                }
-            }
-            counter
-        })
-        // Sum them.
-        .sum();
+fn calculate_ars<T: MatchingStatistics>(string: &str, reference: &T) -> f64 {
+    // Sum the per-position scores.
+    let ars: f64 = ars_profile(string, reference).iter().sum();
     // Do the division and return
     ars / (string.len() as f64)
 }
 
+// Scores each position of a string by the length of the longest substring starting there that is
+// found in the reference.
+pub fn ars_profile<T: MatchingStatistics>(string: &str, reference: &T) -> Vec<f64> {
+    c![i as f64, for i in reference.match_lengths(string)]
+}
+
+fn hamming_distance(a: &[u8], b: &[u8]) -> usize {
+    // Counts the codons at which two encoded sequences differ.
+    a.iter().zip(b.iter()).filter(|(i, j)| i != j).count()
+}
+
+// Views a codon code as a string, to look it up in the codon maps without allocating.
+fn code(byte: &u8) -> &str {
+    std::str::from_utf8(std::slice::from_ref(byte)).unwrap()
+}
+
+/// A design reported at the end of a run.
 #[derive(Debug, Clone, PartialEq)]
-struct Candidate {
-    sequence: String,
-    fitness: Option<f64>,
+pub struct Design {
+    pub sequence: String,
+    pub fitness: f64,
+    // ARS against each host, in the order the hosts were given.
+    pub host_scores: Vec<f64>,
+    // Score for each objective, combined across hosts.
+    pub objective_scores: Vec<f64>,
+}
+
+// Widens a span of changed positions to cover another.
+fn widen(changed: Option<(usize, usize)>, start: usize, end: usize) -> Option<(usize, usize)> {
+    match changed {
+        Some(t) => Some((t.0.min(start), t.1.max(end))),
+        None => Some((start, end)),
+    }
+}
+
+// Finds the span of positions at which two encoded sequences differ, if any.
+fn changed_span(a: &[u8], b: &[u8]) -> Option<(usize, usize)> {
+    let start = (0..a.len()).find(|i| a[*i] != b[*i])?;
+    let end = (0..a.len()).rev().find(|i| a[*i] != b[*i]).unwrap();
+    Some((start, end + 1))
+}
+
+// The scores of a sequence, after any repairs, kept in the fitness cache.
+#[derive(Debug, Clone)]
+pub struct Scored {
+    sequence: Vec<u8>,
+    fitness: f64,
+    host_scores: Vec<f64>,
+    objective_scores: Vec<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Candidate {
+    // One byte per codon, from the same alphabet as the host encodings.
+    pub sequence: Vec<u8>,
+    pub fitness: Option<f64>,
+    host_scores: Vec<f64>,
+    objective_scores: Vec<f64>,
+    // Matching statistics against each host, and the span of positions changed since they were
+    // found, so that only the matches overlapping the changes need to be found again.
+    matches: Vec<Matches>,
+    changed: Option<(usize, usize)>,
 }
 impl Candidate {
     pub fn new(sequence: String) -> Candidate {
+        Candidate {
+            sequence: sequence.into_bytes(),
+            ..Default::default()
+        }
+    }
+
+    // The sequence as an encoded string.
+    fn encoded(&self) -> &str {
+        std::str::from_utf8(&self.sequence).unwrap()
+    }
+
+    fn derive(&self, sequence: Vec<u8>, changed: Option<(usize, usize)>) -> Candidate {
+        // Makes a new candidate from this one, keeping its matching statistics to update.
+        let changed = match changed {
+            Some(t) => widen(self.changed, t.0, t.1),
+            None => self.changed,
+        };
         Candidate {
             sequence,
-            fitness: None,
+            matches: self.matches.clone(),
+            changed,
+            ..Default::default()
         }
     }
 
-    pub fn mutate(&self, n: usize, alternative_map: &HashMap<&str, Vec<&str>>) -> Candidate {
-        // Make a hash set to have chosen positions
-        let mut chosen: HashMap<usize, &str> = HashMap::new();
-        // Create an RNG instance.
-        let mut rng = thread_rng();
+    pub fn mutate(
+        &self,
+        n: usize,
+        alternative_map: &HashMap<&str, Vec<&str>>,
+        locked: &[bool],
+        rng: &mut StdRng,
+    ) -> Candidate {
+        // Make a hash set to have chosen positions, and edit a copy of the sequence in place.
+        let mut chosen: HashSet<usize> = HashSet::new();
+        let mut new_seq = self.sequence.clone();
         // Choose a number of mutations.
-        let no_mutations = rng.gen_range(1, n + 1);
+        let mut no_mutations = rng.gen_range(1, n + 1);
+        // Make no more mutations than there are unlocked positions with alternatives, counting
+        // only as many as are needed.
+        no_mutations = self
+            .sequence
+            .iter()
+            .enumerate()
+            .filter(|(idx, i)| locked.get(*idx) != Some(&true) && !alternative_map.get(code(i)).unwrap().is_empty())
+            .take(no_mutations)
+            .count();
         // Select n positions, and check they have alternatives.
         while chosen.len() != no_mutations {
             // Pick a random position.
             let choice = rng.gen_range(0, self.sequence.len());
-            if chosen.contains_key(&choice) {
+            if chosen.contains(&choice) || locked.get(choice) == Some(&true) {
                 continue;
             }
-            // Get the corresponding codon encoding.
-            let codon_code = self.sequence.chars().nth(choice).unwrap().to_string();
-            // Get alternative codon codes.
-            let alternative_codes = alternative_map.get::<str>(&codon_code).unwrap();
+            // Get alternative codon codes for the codon there.
+            let alternative_codes = alternative_map.get(code(&self.sequence[choice])).unwrap();
             // If empty, don't select.
             if alternative_codes.is_empty() {
                 continue;
             }
             // Choose an alternative codon, and record that choice.
-            chosen.insert(choice, alternative_codes.iter().choose(&mut rng).unwrap());
+            new_seq[choice] = alternative_codes.iter().choose(rng).unwrap().as_bytes()[0];
+            chosen.insert(choice);
         }
 
-        // Generate the new sequence
-        let new_seq = self
-            .sequence
-            .chars()
-            .enumerate()
-            // Use map and match to choose either the new char or the old one.
-            .map(|(idx, char)| {
-                if chosen.contains_key(&idx) {
-                    chosen.get(&idx).unwrap().to_string()
-                } else {
-                    char.to_string().clone().to_string()
-                }
-            })
-            .collect::<String>();
-
         // Use the new seq to make a new candidate, and return it.
-        Candidate::new(new_seq)
+        let start = chosen.iter().min().cloned();
+        let end = chosen.iter().max().map(|i| i + 1);
+        self.derive(new_seq, start.zip(end))
     }
 
-    // Carry out a crossover.
-    pub fn crossover(&self, other: &Candidate) -> (Candidate, Candidate) {
-        // Create an RNG instance.
-        let mut rng = thread_rng();
+    // Carry out a crossover. Locked codons are the same in both parents, so are kept in the children.
+    pub fn crossover(&self, other: &Candidate, rng: &mut StdRng) -> (Candidate, Candidate) {
         // Choose two random positions across the length.
         let mut pos1 = rng.gen_range(0, self.sequence.len());
         let mut pos2 = rng.gen_range(0, self.sequence.len());
@@ -109,70 +175,143 @@ impl Candidate {
         }
 
         // Create the new sequences.
-        let new_seq_one = format!(
-            "{}{}{}",
+        let new_seq_one = [
             &self.sequence[..pos1],
             &other.sequence[pos1..pos2],
-            &self.sequence[pos2..]
-        );
-        let new_seq_two = format!(
-            "{}{}{}",
+            &self.sequence[pos2..],
+        ]
+        .concat();
+        let new_seq_two = [
             &other.sequence[..pos1],
             &self.sequence[pos1..pos2],
-            &other.sequence[pos2..]
-        );
+            &other.sequence[pos2..],
+        ]
+        .concat();
 
-        // Generate candidates and return.
-        (Candidate::new(new_seq_one), Candidate::new(new_seq_two))
+        // Generate candidates and return, each changed where the swapped parts differ.
+        let changed = changed_span(&self.sequence[pos1..pos2], &other.sequence[pos1..pos2])
+            .map(|i| (i.0 + pos1, i.1 + pos1));
+        (self.derive(new_seq_one, changed), other.derive(new_seq_two, changed))
     }
 
-    fn score_min(&mut self, suffix_tables: &[SuffixTable]) {
-        let score: f64 = suffix_tables
-            .iter()
-            .map(|i| calculate_ars(&self.sequence, i))
-            .fold(f64::NAN, f64::min);
-
-        self.fitness = Some(score);
+    fn dominates(&self, other: &Candidate) -> bool {
+        // True if this candidate is at least as good for every objective, and better for one.
+        let pairs = self.objective_scores.iter().zip(other.objective_scores.iter());
+        pairs.clone().all(|(i, j)| i >= j) && pairs.clone().any(|(i, j)| i > j)
     }
 
-    fn score_weighted(&mut self, suffix_tables: &[SuffixTable], weights: &Option<Vec<f64>>) {
-        // Calculate ARS for each suffix table, multiply by weight, and sum.
-        let mut score = suffix_tables
-            .iter()
-            .zip(weights.as_ref().unwrap().iter())
-            .map(|(st, weight)| weight * calculate_ars(&self.sequence, st))
-            .sum();
-        // Normalise by the sum of weights.
-        score /= weights.as_ref().unwrap().iter().sum::<f64>();
-        // Set fitness
-        self.fitness = Some(score);
+    fn set_sequence(&mut self, sequence: Vec<u8>) {
+        // Replaces the sequence, marking the codons that differ as changed.
+        if let Some(t) = changed_span(&self.sequence, &sequence) {
+            self.changed = widen(self.changed, t.0, t.1);
+        }
+        self.sequence = sequence;
     }
 
-    pub fn score(
-        &mut self,
-        suffix_tables: &[SuffixTable],
-        method: &str,
-        weights: &Option<Vec<f64>>,
-    ) {
+    pub fn score(&mut self, indexes: &[MatchIndex], params: &Parameters, cache: &Cache<Vec<u8>, Scored>) {
         if let Some(_t) = self.fitness {
             return;
         }
+        // Reuse the scores of a sequence seen before.
+        if let Some(t) = cache.get(self.sequence.as_slice()) {
+            self.set_sequence(t.sequence);
+            self.fitness = Some(t.fitness);
+            self.host_scores = t.host_scores;
+            self.objective_scores = t.objective_scores;
+            return;
+        }
+        let key = self.sequence.clone();
+        let constraints = params.constraints;
 
-        if method == "weighted" {
-            match weights {
-                Some(t) => {
-                    if t.len() != suffix_tables.len() {
-                        panic!("Length of weights and number of organisms given must be equal in weighted mode")
-                    }
-                }
-                None => panic!("Weighted mode requires weights to be given"),
+        // Repair any broken constraints before scoring.
+        let mut unrepaired = None;
+        if constraints.needs_repair() {
+            let (repaired, violations) = constraints.repair(self.encoded(), params.locked);
+            self.set_sequence(repaired.into_bytes());
+            unrepaired = Some(violations);
+        }
+
+        // Find the matching statistics against each host, updating only the changed windows of
+        // those inherited from a parent.
+        if self.matches.len() != indexes.len() {
+            self.matches = c![i.matches(&self.sequence), for i in indexes];
+        } else if let Some(t) = self.changed {
+            for (index, matches) in indexes.iter().zip(self.matches.iter_mut()) {
+                index.update(&self.sequence, matches, t.0, t.1);
             }
-            self.score_weighted(suffix_tables, weights)
-        } else if method == "min" {
-            self.score_min(suffix_tables)
+        }
+        self.changed = None;
+
+        // Calculate ARS for each host, keeping the per-position scores if they are needed.
+        let profiles = if params.objectives.needs_profiles() {
+            c![c![*j as f64, for j in i.lengths()], for i in &self.matches]
         } else {
-            panic!("Method given for scoring is not supported")
+            vec![]
+        };
+        self.host_scores = c![
+            i.lengths().iter().map(|j| *j as f64).sum::<f64>() / self.sequence.len() as f64,
+            for i in &self.matches
+        ];
+
+        // Score each objective against each host, and combine the hosts.
+        self.objective_scores = params
+            .objectives
+            .kinds
+            .iter()
+            .map(|i| {
+                let scores =
+                    params.objectives.host_scores(*i, self.encoded(), &self.host_scores, &profiles);
+                combine_hosts(&scores, params.method, params.weights)
+            })
+            .collect();
+        let fitness = params.objectives.combine(&self.objective_scores);
+
+        // Penalise or reject candidates that fall short of thresholds or break constraints.
+        let adjusted = params
+            .objectives
+            .adjust_fitness(self.encoded(), &self.host_scores, &profiles, fitness);
+        let adjusted = constraints.adjust_fitness(self.encoded(), adjusted, unrepaired.as_deref());
+        if adjusted.is_finite() {
+            // Penalties apply to each objective too, so they count in Pareto selection.
+            let penalty = fitness - adjusted;
+            self.objective_scores.iter_mut().for_each(|i| *i -= penalty);
         }
+        self.fitness = Some(adjusted);
+        cache.insert(
+            key,
+            Scored {
+                sequence: self.sequence.clone(),
+                fitness: adjusted,
+                host_scores: self.host_scores.clone(),
+                objective_scores: self.objective_scores.clone(),
+            },
+        );
+    }
+}
+
+fn combine_hosts(scores: &[f64], method: &str, weights: &Option<Vec<f64>>) -> f64 {
+    if method == "weighted" {
+        let weights = match weights {
+            Some(t) => {
+                if t.len() != scores.len() {
+                    panic!("Length of weights and number of organisms given must be equal in weighted mode")
+                }
+                t
+            }
+            None => panic!("Weighted mode requires weights to be given"),
+        };
+        // Multiply the score for each host by its weight, and sum.
+        let score: f64 = scores
+            .iter()
+            .zip(weights.iter())
+            .map(|(score, weight)| weight * score)
+            .sum();
+        // Normalise by the sum of weights.
+        score / weights.iter().sum::<f64>()
+    } else if method == "min" {
+        scores.iter().cloned().fold(f64::NAN, f64::min)
+    } else {
+        panic!("Method given for scoring is not supported")
     }
 }
 
@@ -180,6 +319,7 @@ fn generate_random_candidates(
     protein: &str,
     map: &HashMap<&str, Vec<&str>>,
     n: usize,
+    rng: &mut StdRng,
 ) -> Vec<Candidate> {
     // Generates n random candidates encoding protein using map.
     (0..n)
@@ -188,8 +328,6 @@ fn generate_random_candidates(
             protein
                 .chars()
                 .map(|i| {
-                    // Create an RNG instance.
-                    let mut rng = thread_rng();
                     // Convert character to string
                     let str = i.to_string();
                     // Select a random codon encoding that (AA)
@@ -197,7 +335,7 @@ fn generate_random_candidates(
                         .get::<str>(&str)
                         .unwrap()
                         .iter()
-                        .choose(&mut rng)
+                        .choose(rng)
                         .unwrap();
                     *choice
                 })
@@ -207,10 +345,124 @@ fn generate_random_candidates(
         .collect::<Vec<Candidate>>()
 }
 
-fn binary_tournament(population: &mut Vec<Candidate>, target_size: usize) {
-    // Make an RNG instance
-    let mut rng = thread_rng();
-    while population.len() > target_size {
+fn pareto_ranks(population: &[Candidate]) -> Vec<(usize, f64)> {
+    // Ranks candidates by the Pareto front they lie on, and by their crowding distance within it,
+    // as in NSGA-II. Rejected candidates come last.
+    let mut ranks = vec![(usize::MAX, 0.0); population.len()];
+    let valid = c![i, for i in 0..population.len(), if population[i].fitness.unwrap().is_finite()];
+
+    // Count the candidates dominating each one, and list those each one dominates, comparing each
+    // pair once.
+    let mut counts = vec![0; population.len()];
+    let mut dominated: Vec<Vec<usize>> = vec![vec![]; population.len()];
+    for (idx, i) in valid.iter().enumerate() {
+        for j in &valid[idx + 1..] {
+            if population[*i].dominates(&population[*j]) {
+                dominated[*i].push(*j);
+                counts[*j] += 1;
+            } else if population[*j].dominates(&population[*i]) {
+                dominated[*j].push(*i);
+                counts[*i] += 1;
+            }
+        }
+    }
+
+    // The first front is the candidates nothing dominates, and each later front those dominated
+    // only by candidates on earlier fronts.
+    let mut front = c![*i, for i in &valid, if counts[*i] == 0];
+    let mut rank = 0;
+    while !front.is_empty() {
+        for i in &front {
+            ranks[*i] = (rank, 0.0);
+        }
+
+        // Candidates at the ends of the front, or far from their neighbours, are the least crowded.
+        for k in 0..population[front[0]].objective_scores.len() {
+            let mut sorted = front.clone();
+            let score = |i: usize| population[i].objective_scores[k];
+            sorted.sort_by(|a, b| score(*a).partial_cmp(&score(*b)).unwrap());
+            let (min, max) = (score(sorted[0]), score(sorted[sorted.len() - 1]));
+            ranks[sorted[0]].1 = f64::INFINITY;
+            ranks[sorted[sorted.len() - 1]].1 = f64::INFINITY;
+            if max > min {
+                for idx in 1..sorted.len().saturating_sub(1) {
+                    ranks[sorted[idx]].1 += (score(sorted[idx + 1]) - score(sorted[idx - 1])) / (max - min);
+                }
+            }
+        }
+
+        let mut next = vec![];
+        for i in &front {
+            for j in &dominated[*i] {
+                counts[*j] -= 1;
+                if counts[*j] == 0 {
+                    next.push(*j);
+                }
+            }
+        }
+        // Keep each front in population order.
+        next.sort_unstable();
+        front = next;
+        rank += 1;
+    }
+    ranks
+}
+
+fn shared_fitness(population: &[Candidate], radius: usize) -> Vec<f64> {
+    // Divides each candidate's fitness, above the least fit, by the number of candidates within
+    // radius codons of it, each counted by how close it is.
+    let worst = population
+        .iter()
+        .map(|i| i.fitness.unwrap())
+        .filter(|i| i.is_finite())
+        .fold(f64::NAN, f64::min);
+    // Each pair is compared once, counting differences only up to the radius.
+    let mut niches = vec![1.0; population.len()];
+    for i in 0..population.len() {
+        for j in i + 1..population.len() {
+            let (a, b) = (&population[i].sequence, &population[j].sequence);
+            let d = a.iter().zip(b.iter()).filter(|(k, l)| k != l).take(radius).count();
+            if d < radius {
+                let share = 1.0 - d as f64 / radius as f64;
+                niches[i] += share;
+                niches[j] += share;
+            }
+        }
+    }
+    population
+        .iter()
+        .zip(niches)
+        .map(|(i, niche)| {
+            let fitness = i.fitness.unwrap();
+            if fitness.is_finite() {
+                (fitness - worst) / niche
+            } else {
+                fitness
+            }
+        })
+        .collect()
+}
+
+fn binary_tournament(
+    population: &mut Vec<Candidate>,
+    target_size: usize,
+    pareto: bool,
+    sharing: Option<usize>,
+    rng: &mut StdRng,
+) {
+    // In Pareto mode, candidates on better fronts win, then the less crowded.
+    let mut ranks = if pareto {
+        pareto_ranks(population)
+    } else {
+        vec![]
+    };
+    // With fitness sharing, candidates in crowded niches compete with less of their fitness.
+    let mut fitnesses = match sharing {
+        Some(t) if !pareto && population.len() > target_size => shared_fitness(population, t),
+        _ => c![i.fitness.unwrap(), for i in population.iter()],
+    };
+    // A pair can only be picked from two or more candidates.
+    while population.len() > target_size && population.len() > 1 {
         // Choose two random members of the population.
         let member1_idx = rng.gen_range(0, population.len());
         let mut member2_idx = rng.gen_range(0, population.len());
@@ -222,33 +474,33 @@ fn binary_tournament(population: &mut Vec<Candidate>, target_size: usize) {
         let member1 = population.get(member1_idx).unwrap();
         let member2 = population.get(member2_idx).unwrap();
 
-
-        let member1_fitness = member1.fitness.unwrap();
-        let member2_fitness = member2.fitness.unwrap();
-
-        if member1_fitness > member2_fitness {
-            let to_remove = population
-                .iter()
-                .enumerate()
-                .find(|(_, item)| item == &member2)
-                .unwrap()
-                .0;
-            population.remove(to_remove);
+        let member1_wins = if pareto {
+            let (rank1, rank2) = (ranks[member1_idx], ranks[member2_idx]);
+            rank1.0 < rank2.0 || (rank1.0 == rank2.0 && rank1.1 > rank2.1)
         } else {
-            let to_remove = population
-                .iter()
-                .enumerate()
-                .find(|(_, item)| item == &member1)
-                .unwrap()
-                .0;
-            population.remove(to_remove);
+            fitnesses[member1_idx] > fitnesses[member2_idx]
+        };
+
+        // Remove the first candidate with the loser's sequence, which has the same scores.
+        let loser = if member1_wins { member2 } else { member1 };
+        let to_remove = population
+            .iter()
+            .enumerate()
+            .find(|(_, item)| item.sequence == loser.sequence)
+            .unwrap()
+            .0;
+        population.remove(to_remove);
+        fitnesses.remove(to_remove);
+        if pareto {
+            ranks.remove(to_remove);
         }
     }
 }
 
-fn crossovers(population: &mut Vec<Candidate>, n_mut: usize) {
-    // Make an RNG instance
-    let mut rng = thread_rng();
+fn crossovers(population: &mut Vec<Candidate>, n_mut: usize, rng: &mut StdRng) {
+    if population.len() < 2 {
+        return;
+    }
     for _ in 0..n_mut {
         // Choose two random members of the population.
         let member1_idx = rng.gen_range(0, population.len());
@@ -261,7 +513,7 @@ fn crossovers(population: &mut Vec<Candidate>, n_mut: usize) {
         let member1 = population.get(member1_idx).unwrap();
         let member2 = population.get(member2_idx).unwrap();
 
-        let (new_a, new_b) = member1.crossover(member2);
+        let (new_a, new_b) = member1.crossover(member2, rng);
         population.push(new_a);
         population.push(new_b)
     }
@@ -271,96 +523,791 @@ fn mutations(
     population: &mut Vec<Candidate>,
     alternative_map: &HashMap<&str, Vec<&str>>,
     n_cross: usize,
+    locked: &[bool],
+    rng: &mut StdRng,
 ) {
-    // Make an RNG instance
-    let mut rng = thread_rng();
     for _ in 0..n_cross {
         // Choose a random member of the population.
-        let member = population.iter().choose(&mut rng).unwrap();
-        let new_a = member.mutate(5, alternative_map);
+        let member = population.iter().choose(rng).unwrap();
+        let new_a = member.mutate(5, alternative_map, locked, rng);
         population.push(new_a);
     }
 }
 
-pub fn run_ea(
-    protein: &str,
-    suffix_tables: &[SuffixTable],
-    amino_singular: &HashMap<&str, Vec<&str>>,
-    alternatives: &HashMap<&str, Vec<&str>>,
-    n_cross: usize,
-    n_mut: usize,
-    n_gen: usize,
-    n_gen_start: usize,
-    method: &str,
-    weights: &Option<Vec<f64>>,
-) -> (String, f64) {
-    // Start by generating random candidates equal to (n_cross * 2) + (n_mut) --> number of new candidates in one generation.
-    let mut population = generate_random_candidates(protein, amino_singular, (n_cross * 2) + n_mut);
-    population
-        .iter_mut()
-        .for_each(|i| i.score(suffix_tables, method, weights));
+fn crowding(
+    population: &mut [Candidate],
+    indexes: &[MatchIndex],
+    alternative_map: &HashMap<&str, Vec<&str>>,
+    params: &Parameters,
+    cache: &Cache<Vec<u8>, Scored>,
+    rng: &mut StdRng,
+) {
+    // Deterministic crowding: each child competes only with the parent it is most like, and
+    // replaces it if fitter, so that similar candidates do not crowd out the rest.
+    let mut children: Vec<(usize, Candidate)> = vec![];
+    // Crossover needs a pair of parents, so is skipped below two candidates.
+    let n_cross = if population.len() < 2 { 0 } else { params.n_cross };
+    for _ in 0..n_cross {
+        let member1_idx = rng.gen_range(0, population.len());
+        let mut member2_idx = rng.gen_range(0, population.len());
+        while member1_idx == member2_idx {
+            member2_idx = rng.gen_range(0, population.len());
+        }
+        let (member1, member2) = (&population[member1_idx], &population[member2_idx]);
+        let (new_a, new_b) = member1.crossover(member2, rng);
+        let distance = |i: &Candidate, j: &Candidate| hamming_distance(&i.sequence, &j.sequence);
+        if distance(member1, &new_a) + distance(member2, &new_b)
+            <= distance(member1, &new_b) + distance(member2, &new_a)
+        {
+            children.push((member1_idx, new_a));
+            children.push((member2_idx, new_b));
+        } else {
+            children.push((member1_idx, new_b));
+            children.push((member2_idx, new_a));
+        }
+    }
+    for _ in 0..params.n_mut {
+        let member_idx = rng.gen_range(0, population.len());
+        let new_a = population[member_idx].mutate(5, alternative_map, params.locked, rng);
+        children.push((member_idx, new_a));
+    }
 
-    let highest_fitness = &population
+    children
+        .par_iter_mut()
+        .for_each(|i| i.1.score(indexes, params, cache));
+    for (parent, child) in children {
+        let fitter = if params.objectives.pareto {
+            child.dominates(&population[parent])
+        } else {
+            child.fitness.unwrap() > population[parent].fitness.unwrap()
+        };
+        if fitter {
+            population[parent] = child;
+        }
+    }
+}
+
+fn generate_seeded_candidates(
+    seeds: &[String],
+    alternative_map: &HashMap<&str, Vec<&str>>,
+    n: usize,
+    locked: &[bool],
+    rng: &mut StdRng,
+) -> Vec<Candidate> {
+    // Generates n candidates from the seed sequences. Each seed is included once as given, and
+    // any remaining slots are filled with mutated copies of the seeds.
+    (0..n)
+        .map(|i| {
+            let seed = Candidate::new(seeds[i % seeds.len()].clone());
+            if i < seeds.len() {
+                seed
+            } else {
+                seed.mutate(5, alternative_map, locked, rng)
+            }
+        })
+        .collect::<Vec<Candidate>>()
+}
+
+pub fn apply_template(sequence: &str, template: &str, locked: &[bool]) -> String {
+    // Replaces the codons at locked positions with those of the template.
+    sequence
+        .chars()
+        .zip(template.chars())
+        .enumerate()
+        .map(|(idx, (i, j))| if locked.get(idx) == Some(&true) { j } else { i })
+        .collect::<String>()
+}
+
+pub fn select_designs(
+    population: &[Candidate],
+    n: usize,
+    min_distance: usize,
+    pareto: bool,
+) -> Vec<Design> {
+    // Picks up to n of the fittest distinct candidates, each at least min_distance codons from the others.
+    // In Pareto mode, candidates on better Pareto fronts come first. Rejected candidates are never picked.
+    let fronts = if pareto {
+        c![i.0, for i in pareto_ranks(population)]
+    } else {
+        vec![0; population.len()]
+    };
+    let mut ranked = population
+        .iter()
+        .zip(fronts)
+        .filter(|i| i.0.fitness.unwrap().is_finite())
+        .collect::<Vec<(&Candidate, usize)>>();
+    ranked.sort_by(|a, b| {
+        a.1.cmp(&b.1)
+            .then(b.0.fitness.unwrap().partial_cmp(&a.0.fitness.unwrap()).unwrap())
+    });
+    let ranked = c![i.0, for i in ranked];
+
+    let mut chosen: Vec<&Candidate> = vec![];
+    for candidate in ranked {
+        if chosen.len() == n {
+            break;
+        }
+        if chosen.iter().all(|i| {
+            i.sequence != candidate.sequence
+                && hamming_distance(&i.sequence, &candidate.sequence) >= min_distance
+        }) {
+            chosen.push(candidate);
+        }
+    }
+
+    c![Design {
+        sequence: i.encoded().to_string(),
+        fitness: i.fitness.unwrap(),
+        host_scores: i.host_scores.clone(),
+        objective_scores: i.objective_scores.clone(),
+    }, for i in chosen]
+}
+
+fn population_diversity<T: Borrow<Candidate>>(population: &[T]) -> f64 {
+    // Calculates the mean proportion of codons that differ between pairs of candidates.
+    // Counting codons per position avoids comparing every pair of candidates.
+    let n = population.len();
+    if n < 2 {
+        return 0.0;
+    }
+    let sequences = c![&i.borrow().sequence, for i in population];
+    let length = sequences[0].len();
+    let mut differing_pairs = 0.0;
+    for pos in 0..length {
+        let mut counts = [0.0; 256];
+        for sequence in &sequences {
+            counts[sequence[pos] as usize] += 1.0;
+        }
+        let same_pairs: f64 = counts.iter().map(|i| i * (i - 1.0) / 2.0).sum();
+        differing_pairs += (n * (n - 1) / 2) as f64 - same_pairs;
+    }
+    differing_pairs / ((n * (n - 1) / 2) as f64 * length as f64)
+}
+
+pub fn deduplicate(population: &mut Vec<Candidate>) {
+    // Removes candidates with the same sequence as one earlier in the population.
+    let mut seen = HashSet::new();
+    population.retain(|i| seen.insert(i.sequence.clone()));
+}
+
+fn refill(
+    population: &mut Vec<Candidate>,
+    size: usize,
+    problem: &Problem,
+    cache: &Cache<Vec<u8>, Scored>,
+    rng: &mut StdRng,
+) {
+    // Tops the population back up to size with mutated copies of its members, or with random
+    // candidates if it is empty, so that deduplication cannot shrink it for good.
+    let params = problem.params;
+    let n = size.saturating_sub(population.len());
+    if n == 0 {
+        return;
+    }
+    let mut new = if population.is_empty() {
+        let mut random = generate_random_candidates(problem.protein, problem.amino_singular, n, rng);
+        if let Some(t) = params.template {
+            random
+                .iter_mut()
+                .for_each(|i| i.sequence = apply_template(i.encoded(), t, params.locked).into_bytes());
+        }
+        random
+    } else {
+        (0..n)
+            .map(|_| {
+                let member = population.iter().choose(rng).unwrap();
+                member.mutate(5, problem.alternatives, params.locked, rng)
+            })
+            .collect::<Vec<Candidate>>()
+    };
+    new.par_iter_mut()
+        .for_each(|i| i.score(problem.indexes, params, cache));
+    population.extend(new);
+}
+
+pub fn summarise(generation: usize, population: &[&Candidate], cache_hit_rate: f64) -> GenerationStats {
+    // Summarises the fitness and diversity of a scored population.
+    let worst = population
         .iter()
         .map(|i| i.fitness.unwrap())
-        .fold(f64::NAN, f64::max);
+        .fold(f64::NAN, f64::min);
+    // Rejected candidates are left out of the mean and median.
+    let mut fitnesses = c![i.fitness.unwrap(), for i in population, if i.fitness.unwrap().is_finite()];
+    fitnesses.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let n = fitnesses.len();
+    let median = if n == 0 {
+        f64::NAN
+    } else if n % 2 == 0 {
+        (fitnesses[n / 2 - 1] + fitnesses[n / 2]) / 2.0
+    } else {
+        fitnesses[n / 2]
+    };
+    let n_hosts = population[0].host_scores.len();
+    let host_best = c![
+        population.iter().map(|i| i.host_scores[j]).fold(f64::NAN, f64::max),
+        for j in 0..n_hosts];
+    let unique = population
+        .iter()
+        .map(|i| i.sequence.as_slice())
+        .collect::<HashSet<&[u8]>>()
+        .len();
+
+    GenerationStats {
+        generation,
+        best: fitnesses.last().cloned().unwrap_or(worst),
+        mean: fitnesses.iter().sum::<f64>() / n as f64,
+        median,
+        worst,
+        host_best,
+        diversity: population_diversity(population),
+        unique,
+        cache_hit_rate,
+    }
+}
+
+/// Parameters controlling a run of the evolutionary algorithm.
+pub struct Parameters<'a> {
+    pub n_cross: usize,
+    pub n_mut: usize,
+    pub n_gen: usize,
+    pub n_gen_start: usize,
+    pub method: &'a str,
+    pub weights: &'a Option<Vec<f64>>,
+    // Encoded sequences to inject into generation 0, and the proportion of generation 0 they make up.
+    pub seeds: &'a [String],
+    pub seed_fraction: f64,
+    // Number of designs to report, and the minimum number of codons by which they must differ.
+    pub top: usize,
+    pub min_distance: usize,
+    // Seed for the random number generator, so runs can be reproduced.
+    pub random_seed: u64,
+    // Stop early if the best score has not improved for this many generations.
+    pub stall: Option<usize>,
+    // Number of scored sequences to cache, and whether to remove duplicate candidates.
+    pub cache_size: usize,
+    pub dedup: bool,
+    // Number of sub-populations, how often and how many of their fittest candidates migrate, and
+    // between which islands.
+    pub islands: usize,
+    pub migration_interval: usize,
+    pub migrants: usize,
+    pub topology: Topology,
+    // How to keep the population diverse, the radius in codons of a niche for fitness sharing, and
+    // the diversity below which an island restarts.
+    pub diversity: Diversity,
+    pub sharing_radius: usize,
+    pub restart_diversity: Option<f64>,
+    pub constraints: &'a Constraints<'a>,
+    pub objectives: &'a Objectives,
+    // Encoded template CDS, and the positions at which its codons must be kept.
+    pub template: Option<&'a str>,
+    pub locked: &'a [bool],
+}
+
+/// Why a run of the evolutionary algorithm finished.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    GenerationLimit,
+    Stalled,
+}
+
+impl StopReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StopReason::GenerationLimit => "generation_limit",
+            StopReason::Stalled => "stalled",
+        }
+    }
+}
+
+/// The result of a run of the evolutionary algorithm.
+pub struct Outcome {
+    pub designs: Vec<Design>,
+    pub generations: usize,
+    pub stop_reason: StopReason,
+    // Proportion of candidates whose scores were found in the fitness cache.
+    pub cache_hit_rate: f64,
+    // Number of times an island was restarted because it lost its diversity.
+    pub restarts: usize,
+}
+
+/// How islands exchange migrants: each with the next in a ring, or every island with every other.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Topology {
+    Ring,
+    Full,
+}
+
+impl Topology {
+    pub fn parse(name: &str) -> Topology {
+        match name {
+            "ring" => Topology::Ring,
+            "full" => Topology::Full,
+            _ => panic!("Topology must be ring or full"),
+        }
+    }
+}
+
+/// How the population is kept diverse: by deterministic crowding, in which children replace only
+/// the parents they are most like, or by sharing fitness between candidates in the same niche.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Diversity {
+    None,
+    Crowding,
+    Sharing,
+}
+
+impl Diversity {
+    pub fn parse(name: &str) -> Diversity {
+        match name {
+            "none" => Diversity::None,
+            "crowding" => Diversity::Crowding,
+            "sharing" => Diversity::Sharing,
+            _ => panic!("Diversity must be none, crowding or sharing"),
+        }
+    }
+}
+
+pub fn initial_population(index: usize, problem: &Problem, rng: &mut StdRng) -> Vec<Candidate> {
+    let params = problem.params;
+    // Start by generating candidates equal to (n_cross * 2) + (n_mut) --> number of new candidates in one generation.
+    let initial_size = (params.n_cross * 2) + params.n_mut;
+    // Split generation 0 between seeded and random candidates.
+    let n_seeded = if params.seeds.is_empty() {
+        0
+    } else {
+        ((initial_size as f64) * params.seed_fraction).round() as usize
+    };
+    let mut population =
+        generate_seeded_candidates(params.seeds, problem.alternatives, n_seeded, params.locked, rng);
+    population.extend(generate_random_candidates(
+        problem.protein,
+        problem.amino_singular,
+        initial_size - n_seeded,
+        rng,
+    ));
     info!(
-        "Generation 0 : score of best candidate = {}",
-        highest_fitness
+        "{} seeded and {} random candidates on island {}",
+        n_seeded,
+        initial_size - n_seeded,
+        index + 1
     );
+    // Every candidate starts with the template's codons at locked positions.
+    if let Some(t) = params.template {
+        population
+            .iter_mut()
+            .for_each(|i| i.sequence = apply_template(i.encoded(), t, params.locked).into_bytes());
+    }
+    population
+}
 
-    // For each of the remaining generations.
-    for gen in 1..n_gen {
-        // Binary tournament.
-        debug!("Generation {}: Carrying out binary tournament", gen);
-        binary_tournament(&mut population, n_gen_start);
-        debug!("Generation {}: Carrying out crossover events", gen);
-        crossovers(&mut population, n_cross);
-        debug!("Generation {}: Carrying out mutation events", gen);
-        mutations(&mut population, alternatives, n_mut);
-        // Score candidates
-        debug!("Generation {}: Scoring candidates", gen);
+// A sub-population, evolving with its own random number generator.
+struct Island {
+    population: Vec<Candidate>,
+    rng: StdRng,
+    restarts: usize,
+}
+
+impl Island {
+    fn new(index: usize, problem: &Problem) -> Island {
+        // The first island uses the run's random seed, and each other island a stream of its own.
+        let mut rng = StdRng::seed_from_u64(problem.params.random_seed.wrapping_add(index as u64));
+        let population = initial_population(index, problem, &mut rng);
+        Island {
+            population,
+            rng,
+            restarts: 0,
+        }
+    }
+
+    fn restart(&mut self, index: usize, problem: &Problem, cache: &Cache<Vec<u8>, Scored>) {
+        // Replaces all but the fittest candidates with a new, scored initial population, keeping
+        // the island the same size.
+        let params = problem.params;
+        let size = self.population.len();
+        self.population
+            .sort_by(|a, b| b.fitness.unwrap().partial_cmp(&a.fitness.unwrap()).unwrap());
+        self.population.truncate(params.top.max(1));
+        let mut population = initial_population(index, problem, &mut self.rng);
+        population.truncate(size.saturating_sub(self.population.len()));
         population
             .par_iter_mut()
-            .for_each(|i| i.score(suffix_tables, method, weights));
+            .for_each(|i| i.score(problem.indexes, params, cache));
+        self.population.extend(population);
+        if params.dedup {
+            deduplicate(&mut self.population);
+        }
+        refill(&mut self.population, size, problem, cache, &mut self.rng);
+        self.restarts += 1;
+    }
 
-        // Report
-        if gen % 100 == 0 {
-            let highest_fitness = &population
-                .iter()
-                .map(|i| i.fitness.unwrap())
-                .fold(f64::NAN, f64::max);
-            info!(
-                "Generation {} : score of best candidate = {}",
-                gen, highest_fitness
-            );
+    fn evolve(&mut self, gen: usize, problem: &Problem, cache: &Cache<Vec<u8>, Scored>) {
+        let (params, indexes, alternatives) = (problem.params, problem.indexes, problem.alternatives);
+        let (population, rng) = (&mut self.population, &mut self.rng);
+        let sharing = if params.diversity == Diversity::Sharing {
+            Some(params.sharing_radius)
+        } else {
+            None
+        };
+        // Binary tournament.
+        debug!("Generation {}: Carrying out binary tournament", gen);
+        binary_tournament(population, params.n_gen_start, params.objectives.pareto, sharing, rng);
+        if params.diversity == Diversity::Crowding {
+            debug!("Generation {}: Replacing parents by crowding", gen);
+            crowding(population, indexes, alternatives, params, cache, rng);
+        } else {
+            debug!("Generation {}: Carrying out crossover events", gen);
+            crossovers(population, params.n_cross, rng);
+            debug!("Generation {}: Carrying out mutation events", gen);
+            mutations(population, alternatives, params.n_mut, params.locked, rng);
+            // Score candidates
+            debug!("Generation {}: Scoring candidates", gen);
+            population
+                .par_iter_mut()
+                .for_each(|i| i.score(indexes, params, cache));
+        }
+        if params.dedup {
+            let size = population.len().min(params.n_gen_start);
+            deduplicate(population);
+            refill(population, size, problem, cache, rng);
         }
     }
+}
 
-    // Get the highest fitness.
-    let highest_fitness = &population
-        .iter()
-        .map(|i| i.fitness.unwrap())
-        .fold(f64::NAN, f64::max);
-    // Report
-    info!(
-        "Algorithm complete -- highest fitness {}",
-        highest_fitness
-    );
+fn migrate(islands: &mut [Island], n: usize, topology: Topology) {
+    // Copies the n fittest candidates of each island to its neighbours, where they replace the
+    // least fit.
+    let k = islands.len();
+    if k < 2 || n == 0 {
+        return;
+    }
+    let emigrants = c![{
+        let mut ranked = c![j, for j in &i.population];
+        ranked.sort_by(|a, b| b.fitness.unwrap().partial_cmp(&a.fitness.unwrap()).unwrap());
+        c![(*j).clone(), for j in ranked.into_iter().take(n)]
+    }, for i in islands.iter()];
+    for (idx, island) in islands.iter_mut().enumerate() {
+        let sources = match topology {
+            Topology::Ring => vec![(idx + k - 1) % k],
+            Topology::Full => c![j, for j in 0..k, if j != idx],
+        };
+        let immigrants = sources.iter().flat_map(|i| emigrants[*i].iter().cloned()).collect::<Vec<Candidate>>();
+        let population = &mut island.population;
+        let mut worst = (0..population.len()).collect::<Vec<usize>>();
+        worst.sort_by(|a, b| {
+            population[*a]
+                .fitness
+                .unwrap()
+                .partial_cmp(&population[*b].fitness.unwrap())
+                .unwrap()
+        });
+        for (i, immigrant) in worst.into_iter().zip(immigrants) {
+            population[i] = immigrant;
+        }
+    }
+}
 
-    // Get the instance with the highest fitness.
-    let best_scoring = c![i, for i in population, if (i.fitness.unwrap() - *highest_fitness).abs() < 1e-12];
-    let best = best_scoring.iter().next().unwrap();
+/// The evolutionary algorithm, evolving one or more islands of candidates.
+#[derive(Default)]
+pub struct Evolution {
+    islands: Vec<Island>,
+}
 
-    (best.sequence.clone(), best.fitness.unwrap())
+impl Optimiser for Evolution {
+    fn start(&mut self, problem: &Problem, cache: &Cache<Vec<u8>, Scored>) {
+        let params = problem.params;
+        self.islands = c![Island::new(i, problem), for i in 0..params.islands.max(1)];
+        self.islands.par_iter_mut().for_each(|island| {
+            island
+                .population
+                .iter_mut()
+                .for_each(|i| i.score(problem.indexes, params, cache));
+            if params.dedup {
+                let size = island.population.len().min(params.n_gen_start);
+                deduplicate(&mut island.population);
+                refill(&mut island.population, size, problem, cache, &mut island.rng);
+            }
+        });
+    }
+
+    fn step(&mut self, gen: usize, problem: &Problem, cache: &Cache<Vec<u8>, Scored>) {
+        // Each island evolves in parallel, restarting if it has lost its diversity, and exchanges
+        // migrants every so often.
+        let params = problem.params;
+        self.islands.par_iter_mut().enumerate().for_each(|(idx, island)| {
+            island.evolve(gen, problem, cache);
+            if let Some(t) = params.restart_diversity {
+                if population_diversity(&island.population) < t {
+                    info!("Generation {} : restarting island {}", gen, idx + 1);
+                    island.restart(idx, problem, cache);
+                }
+            }
+        });
+        if params.migration_interval > 0 && gen.is_multiple_of(params.migration_interval) {
+            debug!("Generation {}: Migrating between islands", gen);
+            migrate(&mut self.islands, params.migrants, params.topology);
+        }
+    }
+
+    fn population(&self) -> Vec<&Candidate> {
+        self.islands.iter().flat_map(|i| &i.population).collect()
+    }
+
+    fn restarts(&self) -> usize {
+        self.islands.iter().map(|i| i.restarts).sum()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
+    use crate::objectives::Objective;
+    use crate::translation_tables;
     use suffix::SuffixTable;
 
+    // Runs f on a small problem for protein, scored against a host of the protein's own
+    // encodings, with duplicate candidates removed each generation.
+    fn with_problem(
+        protein: &str,
+        diversity: Diversity,
+        seeds: &[String],
+        seed_fraction: f64,
+        f: impl FnOnce(&Problem),
+    ) {
+        let (_, singular_codon_map, _, _, amino_singular_map, alternatives) =
+            translation_tables::generate_maps(translation_tables::tt11());
+        let constraints = Constraints::new(&singular_codon_map, &alternatives);
+        let objectives = Objectives::new(vec![Objective::Ars], vec![1.0], false);
+        let mut rng = StdRng::seed_from_u64(1);
+        let host = c![i.encoded().to_string(), for i in generate_random_candidates(protein, &amino_singular_map, 3, &mut rng)];
+        let indexes = vec![MatchIndex::new(&host.join(""))];
+        let params = Parameters {
+            n_cross: 3,
+            n_mut: 4,
+            n_gen: 5,
+            n_gen_start: 8,
+            method: "min",
+            weights: &None,
+            seeds,
+            seed_fraction,
+            top: 1,
+            min_distance: 1,
+            random_seed: 1,
+            stall: None,
+            cache_size: 100,
+            dedup: true,
+            islands: 1,
+            migration_interval: 0,
+            migrants: 0,
+            topology: Topology::Ring,
+            diversity,
+            sharing_radius: 1,
+            restart_diversity: None,
+            constraints: &constraints,
+            objectives: &objectives,
+            template: None,
+            locked: &[],
+        };
+        f(&Problem {
+            protein,
+            indexes: &indexes,
+            amino_singular: &amino_singular_map,
+            alternatives: &alternatives,
+            params: &params,
+        });
+    }
+
+    #[test]
+    fn test_select_designs() {
+        let population = c![Candidate {
+            sequence: i.0.as_bytes().to_vec(),
+            fitness: Some(i.1),
+            host_scores: vec![i.1],
+            objective_scores: vec![i.1],
+            ..Default::default()
+        }, for i in [("AAAA", 1.0), ("AAAB", 4.0), ("AAAB", 4.0), ("ABBB", 3.0), ("BBBB", 2.0)]];
+        let designs = select_designs(&population, 3, 2, false);
+        let sequences = c![i.sequence.as_str(), for i in &designs];
+        assert_eq!(sequences, vec!["AAAB", "ABBB"]);
+
+        // Rejected candidates are left out even when too few others remain.
+        let population = c![Candidate {
+            sequence: i.0.as_bytes().to_vec(),
+            fitness: Some(i.1),
+            host_scores: vec![i.1],
+            objective_scores: vec![i.1],
+            ..Default::default()
+        }, for i in [("AAAA", f64::NEG_INFINITY), ("AAAB", 1.0), ("BBBB", f64::NEG_INFINITY)]];
+        let designs = select_designs(&population, 3, 1, false);
+        let sequences = c![i.sequence.as_str(), for i in &designs];
+        assert_eq!(sequences, vec!["AAAB"]);
+        assert!(select_designs(&population[2..], 3, 1, true).is_empty());
+    }
+    #[test]
+    fn test_pareto_selection() {
+        // BBBB is dominated by ABBB, so AAAA comes second despite its lower fitness.
+        let population = c![Candidate {
+            sequence: i.0.as_bytes().to_vec(),
+            fitness: Some(i.1[0] + i.1[1]),
+            host_scores: vec![i.1[0]],
+            objective_scores: i.1.to_vec(),
+            ..Default::default()
+        }, for i in [("AAAA", [0.5, 0.9]), ("ABBB", [2.0, 0.5]), ("BBBB", [1.5, 0.4])]];
+        assert!(population[1].dominates(&population[2]));
+        assert!(!population[0].dominates(&population[1]));
+        let ranks = pareto_ranks(&population);
+        assert_eq!(c![i.0, for i in &ranks], vec![0, 0, 1]);
+        let designs = select_designs(&population, 2, 1, true);
+        let sequences = c![i.sequence.as_str(), for i in &designs];
+        assert_eq!(sequences, vec!["ABBB", "AAAA"]);
+        let designs = select_designs(&population, 2, 1, false);
+        let sequences = c![i.sequence.as_str(), for i in &designs];
+        assert_eq!(sequences, vec!["ABBB", "BBBB"]);
+    }
+    #[test]
+    fn test_pareto_fronts() {
+        // No candidate is dominated by one on its own or a later front, and every candidate after
+        // the first front is dominated by one on the front before it.
+        let mut rng = StdRng::seed_from_u64(5);
+        let population = c![Candidate {
+            fitness: Some(if rng.gen_range(0, 10) == 0 { f64::NEG_INFINITY } else { 0.0 }),
+            objective_scores: c![rng.gen_range(0, 5) as f64, for _j in 0..3],
+            ..Default::default()
+        }, for _i in 0..60];
+        let fronts = c![i.0, for i in pareto_ranks(&population)];
+        for (i, a) in population.iter().enumerate() {
+            if !a.fitness.unwrap().is_finite() {
+                assert_eq!(fronts[i], usize::MAX);
+                continue;
+            }
+            let valid = c![j, for j in 0..population.len(), if population[j].fitness.unwrap().is_finite()];
+            assert!(valid.iter().all(|j| fronts[*j] < fronts[i] || !population[*j].dominates(a)));
+            if fronts[i] > 0 {
+                assert!(valid.iter().any(|j| fronts[*j] == fronts[i] - 1 && population[*j].dominates(a)));
+            }
+        }
+        assert!(fronts.iter().any(|i| *i > 2 && *i != usize::MAX));
+    }
+    #[test]
+    fn test_locked_positions() {
+        let alternatives = c! {i.0 => i.1.to_vec(), for i in [("A", ["B"]), ("B", ["A"])]};
+        let locked = [true, true, false, false];
+        let mut rng = StdRng::seed_from_u64(1);
+        let candidate = Candidate::new(apply_template("BBBB", "AAAA", &locked));
+        assert_eq!(candidate.encoded(), "AABB");
+        for _ in 0..20 {
+            let mutant = candidate.mutate(5, &alternatives, &locked, &mut rng);
+            assert_eq!(&mutant.encoded()[..2], "AA");
+            assert_ne!(&mutant.encoded()[2..], "BB");
+        }
+        // No mutations can be made if every position is locked.
+        let mutant = candidate.mutate(5, &alternatives, &[true; 4], &mut rng);
+        assert_eq!(mutant.encoded(), "AABB");
+    }
+    #[test]
+    fn test_migration() {
+        let island = |fitnesses: &[f64], seed: u64| Island {
+            population: c![Candidate {
+                sequence: format!("{}", i).into_bytes(),
+                fitness: Some(*i),
+                ..Default::default()
+            }, for i in fitnesses],
+            rng: StdRng::seed_from_u64(seed),
+            restarts: 0,
+        };
+        let fitnesses = |island: &Island| c![i.fitness.unwrap(), for i in &island.population];
+
+        // Along a ring, each island's best replaces the worst of the next.
+        let mut islands = c![island(&i.0, i.1), for i in [([1.0, 2.0, 3.0], 1), ([4.0, 5.0, 6.0], 2), ([7.0, 8.0, 9.0], 3)]];
+        migrate(&mut islands, 1, Topology::Ring);
+        let expected = [[9.0, 2.0, 3.0], [3.0, 5.0, 6.0], [6.0, 8.0, 9.0]];
+        assert_eq!(c![fitnesses(i), for i in &islands], c![i.to_vec(), for i in &expected]);
+
+        // With every island connected, each receives the best of all the others.
+        let mut islands = c![island(&i.0, i.1), for i in [([1.0, 2.0, 3.0], 1), ([4.0, 5.0, 6.0], 2), ([7.0, 8.0, 9.0], 3)]];
+        migrate(&mut islands, 1, Topology::Full);
+        let expected = [[6.0, 9.0, 3.0], [3.0, 9.0, 6.0], [3.0, 6.0, 9.0]];
+        assert_eq!(c![fitnesses(i), for i in &islands], c![i.to_vec(), for i in &expected]);
+    }
+    #[test]
+    fn test_shared_fitness() {
+        let population = c![Candidate {
+            sequence: i.0.as_bytes().to_vec(),
+            fitness: Some(i.1),
+            ..Default::default()
+        }, for i in [("AAAA", 3.0), ("AAAB", 3.0), ("BBBB", 2.5), ("BABA", f64::NEG_INFINITY)]];
+        // The first two share a niche of radius 2, each counting the other as half, and the
+        // rejected candidate stays rejected.
+        let shared = shared_fitness(&population, 2);
+        assert_eq!(shared, vec![0.5 / 1.5, 0.5 / 1.5, 0.0, f64::NEG_INFINITY]);
+    }
+    #[test]
+    fn test_population_diversity() {
+        let population = c![Candidate::new(i.to_string()), for i in ["AAAA", "AABB", "BBBB"]];
+        // Pairs differ at 2, 4 and 2 of 4 positions.
+        assert_eq!(population_diversity(&population), 2.0 / 3.0);
+        assert_eq!(population_diversity(&population[..1]), 0.0);
+    }
+    #[test]
+    fn test_seeded_population() {
+        // Generation 0 has 10 candidates, of which the seed fraction are the seed or mutated
+        // copies of it. With six codons for each leucine, random candidates are far from the seed.
+        let (codon_singular_map, _, _, _, _, _) = translation_tables::generate_maps(translation_tables::tt11());
+        let seeds = vec![codon_singular_map["CTG"].repeat(12)];
+        for (fraction, n_seeded) in [(0.0, 0), (0.25, 3), (0.5, 5), (1.0, 10)] {
+            with_problem(&"L".repeat(12), Diversity::None, &seeds, fraction, |problem| {
+                let mut rng = StdRng::seed_from_u64(1);
+                let population = initial_population(0, problem, &mut rng);
+                assert_eq!(population.len(), 10);
+                let seeded = c![i, for i in &population, if hamming_distance(&i.sequence, seeds[0].as_bytes()) <= 5];
+                assert_eq!(seeded.len(), n_seeded);
+                if n_seeded > 0 {
+                    assert_eq!(population[0].encoded(), seeds[0]);
+                }
+            });
+        }
+    }
+    #[test]
+    fn test_deduplicated_generation() {
+        // Every encoding of MWMW is the same, so deduplication leaves one candidate, from which
+        // no pair can be picked.
+        with_problem("MWMW", Diversity::None, &[], 0.0, |problem| {
+            let cache = Cache::new(problem.params.cache_size);
+            let mut island = Island::new(0, problem);
+            island
+                .population
+                .iter_mut()
+                .for_each(|i| i.score(problem.indexes, problem.params, &cache));
+            deduplicate(&mut island.population);
+            assert_eq!(island.population.len(), 1);
+            island.evolve(1, problem, &cache);
+            assert_eq!(island.population.len(), 1 + problem.params.n_mut);
+            // The population is topped back up after deduplication.
+            let mut evolution = Evolution::default();
+            evolution.start(problem, &cache);
+            evolution.step(1, problem, &cache);
+            assert_eq!(evolution.population().len(), problem.params.n_gen_start);
+        });
+    }
+    #[test]
+    fn test_crowding_population_size() {
+        // Children replace their parents under crowding, and deduplicated candidates are replaced,
+        // so the island stays the same size.
+        with_problem("MWWMKWMC", Diversity::Crowding, &[], 0.0, |problem| {
+            let cache = Cache::new(problem.params.cache_size);
+            let mut evolution = Evolution::default();
+            evolution.start(problem, &cache);
+            for gen in 1..=problem.params.n_gen {
+                evolution.step(gen, problem, &cache);
+                assert_eq!(evolution.population().len(), problem.params.n_gen_start);
+            }
+            let island = &mut evolution.islands[0];
+            island.restart(0, problem, &cache);
+            assert_eq!(island.population.len(), problem.params.n_gen_start);
+            assert_eq!(island.restarts, 1);
+        });
+    }
     #[test]
     fn test_ars_v1() {
         let st = SuffixTable::new("ACTG");
@@ -385,4 +1332,20 @@ mod tests {
         let string = String::from("ACAC");
         assert_eq!(calculate_ars(&string, &st), 1.5);
     }
+    #[test]
+    fn test_updated_ars() {
+        // Candidates are scored by updating the matching statistics of their parent where they
+        // differ from it, which must give the same ARS as searching a suffix table.
+        let reference = "ACTGACCTGAGTAC";
+        let (st, index) = (SuffixTable::new(reference), MatchIndex::new(reference));
+        let parent = "ACTGACTGACTGAC";
+        for string in ["ACTGACTGACTGAC", "ACTAACTGACTGAC", "AAAAACTGACTGAC", "ACTGACTGACACAC", "CCTGAGTACTGACG"] {
+            let mut matches = index.matches(parent.as_bytes());
+            if let Some(t) = changed_span(parent.as_bytes(), string.as_bytes()) {
+                index.update(string.as_bytes(), &mut matches, t.0, t.1);
+            }
+            let ars = matches.lengths().iter().map(|i| *i as f64).sum::<f64>() / string.len() as f64;
+            assert_eq!(ars, calculate_ars(string, &st));
+        }
+    }
 }
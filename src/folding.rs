@@ -0,0 +1,162 @@
+use std::f64;
+
+// Nearest-neighbour free energies (kcal/mol at 37 C), simplified from the Turner (2004) rules.
+// Pair types are indexed CG, GC, GU, UG, AU, UA.
+const STACK: [[f64; 6]; 6] = [
+    [-2.4, -3.3, -2.1, -1.4, -2.1, -2.1],
+    [-3.3, -3.4, -2.5, -1.5, -2.2, -2.4],
+    [-2.1, -2.5, 1.3, -0.5, -1.4, -1.3],
+    [-1.4, -1.5, -0.5, 0.3, -0.6, -1.0],
+    [-2.1, -2.2, -1.4, -0.6, -1.1, -0.9],
+    [-2.1, -2.4, -1.3, -1.0, -0.9, -1.3],
+];
+// Loop initiation energies by the number of unpaired nucleotides in the loop.
+const HAIRPIN: [f64; 10] = [f64::INFINITY, f64::INFINITY, f64::INFINITY, 5.4, 5.6, 5.7, 5.4, 6.0, 5.5, 6.4];
+const BULGE: [f64; 11] = [f64::INFINITY, 3.8, 2.8, 3.2, 3.6, 4.0, 4.4, 4.59, 4.7, 4.8, 4.9];
+const INTERIOR: [f64; 11] = [f64::INFINITY, f64::INFINITY, 0.5, 1.6, 1.1, 2.0, 2.0, 2.2, 2.3, 2.4, 2.5];
+// Penalties for loops closed by AU or GU pairs, and for asymmetric interior loops.
+const TERMINAL_AU: f64 = 0.5;
+const INTERIOR_AU: f64 = 0.7;
+const ASYMMETRY: f64 = 0.6;
+const MAX_ASYMMETRY: f64 = 3.0;
+// Multiloop closing, unpaired nucleotide and branch energies.
+const MULTI_CLOSING: f64 = 3.4;
+const MULTI_UNPAIRED: f64 = 0.0;
+const MULTI_BRANCH: f64 = 0.4;
+// Longest interior loop considered, and the gas constant times temperature for extrapolating loops.
+const MAX_LOOP: usize = 30;
+const RT: f64 = 0.61632;
+
+fn pair_type(a: u8, b: u8) -> Option<usize> {
+    match (a, b) {
+        (b'C', b'G') => Some(0),
+        (b'G', b'C') => Some(1),
+        (b'G', b'U') => Some(2),
+        (b'U', b'G') => Some(3),
+        (b'A', b'U') => Some(4),
+        (b'U', b'A') => Some(5),
+        _ => None,
+    }
+}
+
+fn is_au(pair: usize) -> bool {
+    // True for AU and GU pairs.
+    pair >= 2
+}
+
+fn loop_energy(table: &[f64], n: usize) -> f64 {
+    // Looks up a loop initiation energy, extrapolating logarithmically beyond the table.
+    if n < table.len() {
+        table[n]
+    } else {
+        let last = table.len() - 1;
+        table[last] + 1.75 * RT * (n as f64 / last as f64).ln()
+    }
+}
+
+fn interior_energy(outer: usize, inner: usize, n1: usize, n2: usize) -> f64 {
+    // The energy of a stack, bulge or interior loop between an outer and an inner pair, where the
+    // inner pair type is read from the inner pair's 3' side.
+    if n1 == 0 && n2 == 0 {
+        return STACK[outer][inner];
+    }
+    let n = n1 + n2;
+    if n1 == 0 || n2 == 0 {
+        if n == 1 {
+            return loop_energy(&BULGE, n) + STACK[outer][inner];
+        }
+        let au = c![TERMINAL_AU, for i in &[outer, inner], if is_au(*i)];
+        return loop_energy(&BULGE, n) + au.iter().sum::<f64>();
+    }
+    let asymmetry = (ASYMMETRY * (n1 as f64 - n2 as f64).abs()).min(MAX_ASYMMETRY);
+    let au = c![INTERIOR_AU, for i in &[outer, inner], if is_au(*i)];
+    loop_energy(&INTERIOR, n) + asymmetry + au.iter().sum::<f64>()
+}
+
+// Estimates the minimum free energy (kcal/mol) of the secondary structure of an RNA or DNA
+// sequence, with a Zuker-style dynamic programme over a nearest-neighbour energy model.
+pub fn mfe(sequence: &str) -> f64 {
+    let sequence = sequence.to_uppercase().replace('T', "U");
+    let s = sequence.as_bytes();
+    let n = s.len();
+    if n < 5 {
+        return 0.0;
+    }
+
+    // v[i][j] is the lowest energy of the region from i to j with i and j paired, and wm[i][j]
+    // that of the region as part of a multiloop.
+    let mut v = vec![vec![f64::INFINITY; n]; n];
+    let mut wm = vec![vec![f64::INFINITY; n]; n];
+    for span in 4..n {
+        for i in 0..n - span {
+            let j = i + span;
+            if let Some(outer) = pair_type(s[i], s[j]) {
+                // A hairpin loop.
+                let au = if is_au(outer) { TERMINAL_AU } else { 0.0 };
+                let mut best = loop_energy(&HAIRPIN, j - i - 1) + au;
+
+                // A stack, bulge or interior loop closed by an inner pair.
+                for k in i + 1..(i + MAX_LOOP + 2).min(j - 4) {
+                    let n1 = k - i - 1;
+                    for l in (k + 4..j).rev() {
+                        let n2 = j - l - 1;
+                        if n1 + n2 > MAX_LOOP {
+                            break;
+                        }
+                        if v[k][l].is_infinite() {
+                            continue;
+                        }
+                        let inner = pair_type(s[l], s[k]).unwrap();
+                        best = best.min(v[k][l] + interior_energy(outer, inner, n1, n2));
+                    }
+                }
+
+                // A multiloop, with at least two branches inside.
+                for k in i + 5..j.saturating_sub(5) {
+                    let branches = wm[i + 1][k] + wm[k + 1][j - 1];
+                    best = best.min(branches + MULTI_CLOSING + MULTI_BRANCH + au);
+                }
+                v[i][j] = best;
+            }
+
+            let au = pair_type(s[i], s[j]).map_or(0.0, |t| if is_au(t) { TERMINAL_AU } else { 0.0 });
+            let mut best = (v[i][j] + MULTI_BRANCH + au)
+                .min(wm[i + 1][j] + MULTI_UNPAIRED)
+                .min(wm[i][j - 1] + MULTI_UNPAIRED);
+            for k in i + 4..j.saturating_sub(4) {
+                best = best.min(wm[i][k] + wm[k + 1][j]);
+            }
+            wm[i][j] = best;
+        }
+    }
+
+    // w[j] is the lowest energy of the first j nucleotides, outside any loop.
+    let mut w = vec![0.0_f64; n + 1];
+    for j in 1..=n {
+        w[j] = w[j - 1];
+        for i in 0..j.saturating_sub(4) {
+            if v[i][j - 1].is_finite() {
+                let pair = pair_type(s[i], s[j - 1]).unwrap();
+                let au = if is_au(pair) { TERMINAL_AU } else { 0.0 };
+                w[j] = w[j].min(w[i] + v[i][j - 1] + au);
+            }
+        }
+    }
+    w[n]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mfe() {
+        // Four GC pairs stacked three times, closing a loop of three.
+        assert!((mfe("GGGGAAACCCC") - (3.0 * -3.3 + 5.4)).abs() < 1e-9);
+        assert_eq!(mfe("GGGGUUUCCCC"), mfe("ggggtttcccc"));
+        assert_eq!(mfe("AAAAAAAAAAAA"), 0.0);
+        assert_eq!(mfe("ACG"), 0.0);
+        // A longer stem folds more stably.
+        assert!(mfe("GGGGGGAAAACCCCCC") < mfe("GGGGAAAACCCC"));
+    }
+}
@@ -9,6 +9,10 @@ extern crate rand;
 extern crate rayon;
 #[macro_use]
 extern crate clap;
+#[macro_use]
+extern crate serde_json;
+extern crate sha2;
+extern crate yaml_rust;
 
 use clap::App;
 use std::collections::HashMap;
@@ -18,36 +22,162 @@ use std::io::{BufWriter, Write};
 use std::time::{Duration, Instant};
 
 
+mod bounds;
+mod cache;
+mod config;
+mod constraints;
 mod ealgorithm;
+mod folding;
+mod matching;
+mod objectives;
+mod optimisers;
 mod preprocessing;
+mod report;
+mod trace;
 mod translation_tables;
 
-fn parse_protein(path: &str) -> String {
+fn parse_protein(path: &str) -> Vec<(String, String)> {
+    // Parses each record in a protein FASTA file to an (ID, sequence) pair.
     let reader = bio::io::fasta::Reader::from_file(path).unwrap();
-    let mut sequence = String::new();
-    for (idx, item) in reader.records().enumerate() {
+    let mut proteins: Vec<(String, String)> = vec![];
+    for item in reader.records() {
         let result = item.unwrap();
-        if idx > 0 {
-            panic!("Too many records in the given FASTA file for protein.");
-        }
-        sequence.push_str(std::str::from_utf8(result.seq()).unwrap());
+        let sequence = std::str::from_utf8(result.seq()).unwrap().to_string();
+        proteins.push((result.id().to_string(), sequence));
+    }
+    if proteins.is_empty() {
+        panic!("No records in the given FASTA file for protein.");
     }
-    sequence
+    proteins
 }
 
-fn parse_cds(path: &str, codon_singular_map: &HashMap<&str, &str>) -> String {
-    info!("{}", format!("Parsing: {}", path));
+fn parse_cds(path: &str, genes: &[String], codon_singular_map: &HashMap<&str, &str>, mut f: impl FnMut(&str)) {
+    // Encodes each record in a CDS FASTA file, and passes it on as it is read.
+    info!("Parsing: {}", path);
     let reader = bio::io::fasta::Reader::from_file(path).unwrap();
-    let mut encoding = String::new();
     for item in reader.records() {
         let result = item.unwrap();
+        // Skip genes that have been filtered out.
+        if !genes.is_empty() && !genes.iter().any(|i| i == result.id()) {
+            continue;
+        }
         let seq = std::str::from_utf8(result.seq()).unwrap();
-        let encoded_seq = preprocessing::translate_codon_str_to_alphabet(seq, &codon_singular_map);
-        encoding.push_str(&encoded_seq);
-        encoding.push('|');
+        let encoded_seq = preprocessing::translate_codon_str_to_alphabet(seq, codon_singular_map);
+        f(&encoded_seq);
     }
+}
 
-    encoding
+fn read_host(
+    path: &str,
+    genes: &[String],
+    codon_singular_map: &HashMap<&str, &str>,
+    proteins: &[(String, String)],
+    amino_singular_map: &HashMap<&str, Vec<&str>>,
+    chunk_size: Option<usize>,
+) -> (objectives::CodonUsage, Vec<String>) {
+    // Counts codon usage as the CDSs are read, and finds the condensed encoding of every protein,
+    // either from a suffix table of all of them that is dropped once done, or a chunk at a time.
+    let mut usage = objectives::CodonUsage::default();
+    match chunk_size {
+        None => {
+            let mut encoding = String::new();
+            parse_cds(path, genes, codon_singular_map, |i| {
+                usage.add(i);
+                encoding.push_str(i);
+                encoding.push('|');
+            });
+            let table = preprocessing::host_table(encoding);
+            (usage, c![preprocessing::condensed_text(&table, &i.1, amino_singular_map), for i in proteins])
+        }
+        Some(t) => {
+            let mut condenser = preprocessing::Condenser::new(c![i.1.as_str(), for i in proteins], amino_singular_map, t);
+            parse_cds(path, genes, codon_singular_map, |i| {
+                usage.add(i);
+                condenser.push(i);
+            });
+            (usage, condenser.finish())
+        }
+    }
+}
+
+fn parse_seeds(
+    kind: &str,
+    path: &str,
+    proteins: &[(String, String)],
+    codon_singular_map: &HashMap<&str, &str>,
+    codon_amino_map: &HashMap<&str, &str>,
+) -> Vec<Vec<String>> {
+    // Parses a FASTA of seed, template or native CDSs, checks each encodes one of the proteins, and
+    // returns their encodings grouped by the protein they encode. Errors name the kind of CDS.
+    info!("Parsing {} file: {}", kind.to_lowercase(), path);
+    let reader = bio::io::fasta::Reader::from_file(path).unwrap();
+    let mut seeds: Vec<Vec<String>> = vec![vec![]; proteins.len()];
+    for item in reader.records() {
+        let result = item.unwrap();
+        let seq = std::str::from_utf8(result.seq()).unwrap().to_uppercase();
+        if !seq.len().is_multiple_of(3) {
+            panic!("{} {} has a length that is not a multiple of three.", kind, result.id());
+        }
+        let codons = c![&seq[i..i + 3], for i in (0..seq.len()).step_by(3)];
+        let translated = codons
+            .iter()
+            .map(|i| match codon_amino_map.get(i) {
+                Some(t) => *t,
+                None => panic!("{} {} contains an invalid codon: {}", kind, result.id(), i),
+            })
+            .collect::<String>();
+        let encoded = c![*codon_singular_map.get(i).unwrap(), for i in codons].concat();
+
+        let mut matched = false;
+        for (idx, (_, protein)) in proteins.iter().enumerate() {
+            if translated == *protein {
+                seeds[idx].push(encoded.clone());
+                matched = true;
+            } else if translated.strip_suffix('*') == Some(protein.as_str()) {
+                // Drop a terminal stop codon if the protein does not include one.
+                seeds[idx].push(encoded[..encoded.len() - 1].to_string());
+                matched = true;
+            }
+        }
+        if !matched {
+            panic!("{} {} does not encode the given protein.", kind, result.id());
+        }
+    }
+    seeds
+}
+
+fn parse_locks(ranges: &[String], mask: &Option<String>, length: usize) -> Vec<bool> {
+    // Marks the codons to keep from the template, from ranges of one-based codon positions
+    // (e.g. 1-6 or 10) and a mask with a 1 for each locked codon.
+    let mut locked = vec![false; length];
+    for range in ranges {
+        let bounds = range
+            .split('-')
+            .map(|i| match i.trim().parse::<usize>() {
+                Ok(t) if t >= 1 && t <= length => t,
+                _ => panic!("Invalid locked range {} for a protein of {} codons", range, length),
+            })
+            .collect::<Vec<usize>>();
+        let (first, last) = match bounds.len() {
+            1 => (bounds[0], bounds[0]),
+            2 if bounds[0] <= bounds[1] => (bounds[0], bounds[1]),
+            _ => panic!("Invalid locked range {}", range),
+        };
+        locked[first - 1..last].iter_mut().for_each(|i| *i = true);
+    }
+    if let Some(t) = mask {
+        if t.chars().count() != length {
+            panic!("Lock mask has {} positions, but the protein has {} codons", t.chars().count(), length);
+        }
+        for (idx, i) in t.chars().enumerate() {
+            match i {
+                '1' => locked[idx] = true,
+                '0' => (),
+                _ => panic!("Lock mask must only contain 0 and 1"),
+            }
+        }
+    }
+    locked
 }
 
 fn setup_logger() {
@@ -55,35 +185,117 @@ fn setup_logger() {
     env_logger::init();
 }
 
-fn encoding_to_nuc(encoded_str: &str, map: &HashMap<&str, &str> ) -> String {
-    let mut nucleotide_string = String::new();
-    encoded_str.chars().for_each(|i| {
-        let i_str = i.to_string();
-        nucleotide_string.push_str( map.get::<str>(&i_str).unwrap() );
-    });
-    nucleotide_string
+/// A design alongside its nucleotide sequence and the checks made on it.
+struct DesignResult {
+    nucleotides: String,
+    design: ealgorithm::Design,
+    // Forbidden sites and motif violations remaining in the design.
+    sites: Vec<constraints::SiteHit>,
+    motifs: Vec<constraints::Violation>,
+    // GC content of the whole design, and where it is out of bounds.
+    gc: f64,
+    gc_violations: Vec<constraints::Violation>,
+    // Repeats remaining in the design, which make it harder to synthesise.
+    repeats: Vec<constraints::Repeat>,
+    // CAI and codon pair bias against each host, and tAI against each host with a tRNA table.
+    cai: Vec<f64>,
+    tai: Vec<Option<f64>>,
+    cpb: Vec<f64>,
+    // Folding energy of the region around the start codon.
+    mfe: f64,
+    // How closely the design matches the native profile against each host, if it was harmonised.
+    harmony: Option<Vec<f64>>,
+    // How far the design falls below the score thresholds.
+    shortfall: f64,
+}
+
+/// The designs found for one protein.
+struct ProteinResult {
+    id: String,
+    length: usize,
+    designs: Vec<DesignResult>,
+    // One-based positions of the codons kept from the template.
+    locked: Vec<usize>,
+    generations: usize,
+    stop_reason: ealgorithm::StopReason,
+    cache_hit_rate: f64,
+    restarts: usize,
+    // The best ARS any encoding could reach against each host, if it was found.
+    bounds: Option<Vec<bounds::Bound>>,
+    duration: Duration,
 }
 
-fn write_to_fasta(outfile: &str, sequence: &str, score: f64, duration: &Duration) -> Result<(), Error> {
+fn write_to_fasta(
+    outfile: &str,
+    results: &[ProteinResult],
+    host_names: &[String],
+) -> Result<(), Error> {
     // Open the outfile and create a buffer.
     let file = File::create(outfile).unwrap();
     let mut buf = BufWriter::new(file);
-    let header = format!(">Result score={} duration={:?}\n", score, duration);
 
-    let mut check = buf.write(header.as_bytes());
-    check.unwrap();
+    for (protein_id, rank, result, duration) in results.iter().flat_map(|i| {
+        i.designs
+            .iter()
+            .enumerate()
+            .map(move |(rank, result)| (&i.id, rank, result, i.duration))
+    }) {
+        // Write one record per design, with its overall and per-host scores in the header.
+        let host_scores = host_names
+            .iter()
+            .zip(result.design.host_scores.iter())
+            .map(|(name, score)| format!(" ars_{}={}", name, score))
+            .collect::<String>();
+        let cai_scores = host_names
+            .iter()
+            .zip(result.cai.iter())
+            .map(|(name, score)| format!(" cai_{}={:.3}", name, score))
+            .collect::<String>();
+        let cpb_scores = host_names
+            .iter()
+            .zip(result.cpb.iter())
+            .map(|(name, score)| format!(" cpb_{}={:.3}", name, score))
+            .collect::<String>();
+        let tai_scores = host_names
+            .iter()
+            .zip(result.tai.iter())
+            .filter_map(|(name, score)| score.map(|t| format!(" tai_{}={:.3}", name, t)))
+            .collect::<String>();
+        let header = format!(
+            ">{}_{} score={}{}{}{}{} sites={} motifs={} gc={:.3} repeats={} mfe={:.1} duration={:?}\n",
+            protein_id,
+            rank + 1,
+            result.design.fitness,
+            host_scores,
+            cai_scores,
+            tai_scores,
+            cpb_scores,
+            result.sites.len(),
+            result.motifs.len(),
+            result.gc,
+            result.repeats.len(),
+            result.mfe,
+            duration
+        );
+        let sequence = &result.nucleotides;
 
-    for (pos, item) in sequence.chars().enumerate() {
+        let mut check = buf.write(header.as_bytes());
+        check.unwrap();
 
-        if pos % 60 == 0 && pos != 0 {
-            check = buf.write("\n".as_bytes());
-            check.unwrap();
-        }
+        for (pos, item) in sequence.chars().enumerate() {
 
-        check = buf.write(item.to_string().as_bytes());
-        check.unwrap();
+            if pos % 60 == 0 && pos != 0 {
+                check = buf.write("\n".as_bytes());
+                check.unwrap();
+            }
+
+            check = buf.write(item.to_string().as_bytes());
+            check.unwrap();
 
 
+        }
+        check = buf.write("\n".as_bytes());
+        check.unwrap();
     }
     buf.flush().unwrap();
 
@@ -97,85 +309,616 @@ fn main() {
     let matches = App::from_yaml(yaml).get_matches();
 
     info!("Parsing command line arguments");
-    // Main files.
-    let genomes: Vec<_> = matches.values_of("cds").unwrap().collect();
-    let protein: &str = matches.value_of("protein").unwrap();
-    // Algorithm parameters (numeric)
-    let no_mutations: usize = matches
-        .value_of("mutations")
-        .unwrap()
-        .parse::<usize>()
-        .unwrap();
-    let no_crossovers: usize = matches
-        .value_of("crossovers")
-        .unwrap()
-        .parse::<usize>()
-        .unwrap();
-    let no_generations: usize = matches
-        .value_of("generations")
-        .unwrap()
-        .parse::<usize>()
-        .unwrap();
-    let gen_start: usize = matches
-        .value_of("generation_start")
-        .unwrap()
-        .parse::<usize>()
-        .unwrap();
-    let outfile: &str = matches
-        .value_of("outfile")
-        .unwrap();
-    // Method and weights.
-    let method = matches.value_of("method").unwrap();
-    let _weights = matches.value_of("weights");
-    let weights: Option<Vec<f64>> = match _weights {
-        Some(t) => Some(
-            t.split(',')
-                .filter_map(|s| s.parse::<f64>().ok())
-                .collect::<Vec<_>>(),
-        ),
-        None => None,
-    };
+    let settings = config::Settings::from_matches(&matches);
 
     // Set up the logger.
     setup_logger();
     info!("Script started");
 
+    run(&settings);
+    info!("Chimera evolve algorithm completed -- thank you for flying Air ICOS! 🚀")
+}
+
+fn run(settings: &config::Settings) {
+    // Designs a CDS for each protein, and writes the outputs asked for.
+    let weights = settings.weights();
     let start = Instant::now();
 
-    info!("Generating maps for codon table 11");
+    info!("Generating maps for codon table {}", settings.table);
+    let table = match translation_tables::by_id(settings.table) {
+        Some(t) => t,
+        None => panic!("Translation table {} is not supported", settings.table),
+    };
     let (
         codon_singular_map,
         singular_codon_map,
-        _codon_amino_map,
+        codon_amino_map,
         _amino_codon_map,
         amino_singular_map,
         alternatives,
-    ) = translation_tables::generate_maps(translation_tables::tt11());
+    ) = translation_tables::generate_maps(table);
 
-    info!("Parsing the protein sequence");
-    let pro_seq = parse_protein(protein);
+    info!("Parsing the protein sequences");
+    let proteins = parse_protein(&settings.protein);
     info!("Parsing and encoding coding sequences");
-    let suffix_tables = c![
-        preprocessing::condense_encoding(
-            &parse_cds(i, &codon_singular_map), &pro_seq, &amino_singular_map
-        ), for i in genomes];
-
-    // Run the genetic algorithm&
-    let (result_seq, result_fitness) = ealgorithm::run_ea(
-        &pro_seq,
-        &suffix_tables,
-        &amino_singular_map,
-        &alternatives,
-        no_crossovers,
-        no_mutations,
-        no_generations,
-        gen_start,
-        method,
-        &weights,
+    // With a memory limit, each host's suffix table is built a chunk at a time.
+    let chunk_size = settings.memory_limit.map(preprocessing::chunk_size);
+    let (host_usages, host_condensed): (Vec<_>, Vec<_>) = settings
+        .hosts
+        .iter()
+        .map(|i| read_host(&i.cds, &i.genes, &codon_singular_map, &proteins, &amino_singular_map, chunk_size))
+        .unzip();
+
+    info!("Parsing seed sequences");
+    let mut seeds: Vec<Vec<String>> = vec![vec![]; proteins.len()];
+    for path in &settings.seeds {
+        for (idx, i) in parse_seeds("Seed", path, &proteins, &codon_singular_map, &codon_amino_map)
+            .into_iter()
+            .enumerate()
+        {
+            seeds[idx].extend(i);
+        }
+    }
+
+    // Parse the template, and the codons to keep from it.
+    let templates = match &settings.template {
+        Some(t) => {
+            info!("Parsing template sequences");
+            c![i.first().cloned(), for i in parse_seeds("Template", t, &proteins, &codon_singular_map, &codon_amino_map)]
+        }
+        None => vec![None; proteins.len()],
+    };
+    // Parse the native CDSs and source organism to harmonise with.
+    let natives = match &settings.native {
+        Some(t) => {
+            info!("Parsing native sequences");
+            c![i.first().cloned(), for i in parse_seeds("Native CDS", t, &proteins, &codon_singular_map, &codon_amino_map)]
+        }
+        None => vec![None; proteins.len()],
+    };
+    let source = settings.source.as_ref().map(|t| {
+        info!("Parsing and encoding source organism coding sequences");
+        read_host(t, &[], &codon_singular_map, &proteins, &amino_singular_map, chunk_size)
+    });
+    let harmony_mode = objectives::HarmonyMode::parse(&settings.harmony_mode);
+
+    let locks = c![parse_locks(&settings.lock, &settings.lock_mask, i.1.len()), for i in &proteins];
+    for ((pro_id, _), (template, locked)) in proteins.iter().zip(templates.iter().zip(locks.iter())) {
+        if template.is_none() && locked.iter().any(|i| *i) {
+            panic!("Codons of {} are locked, but no template encodes it", pro_id);
+        }
+    }
+
+    let mut constraints = constraints::Constraints::new(&singular_codon_map, &alternatives);
+    constraints.sites = c![constraints::Site::parse(i), for i in &settings.sites];
+    constraints.site_mode = constraints::ConstraintMode::parse(&settings.site_mode);
+    constraints.site_penalty = settings.site_penalty;
+    constraints.motifs = c![constraints::Motif::forbidden(i), for i in &settings.motifs];
+    if let Some(t) = settings.max_homopolymer {
+        constraints.motifs.push(constraints::Motif::homopolymer(t));
+    }
+    constraints
+        .motifs
+        .extend(c![constraints::Motif::required(i), for i in &settings.required_motifs]);
+    constraints.motif_mode = constraints::ConstraintMode::parse(&settings.motif_mode);
+    constraints.motif_penalty = settings.motif_penalty;
+    for (pro_id, pro_seq) in &proteins {
+        if let Some(motif) = constraints.motifs.iter().find(|i| !i.fits(3 * pro_seq.len())) {
+            panic!("Required motif {} does not fit in the {} nucleotides of {}", motif.name(), 3 * pro_seq.len(), pro_id);
+        }
+    }
+    if !settings.gc.is_empty() {
+        constraints.gc = Some(constraints::GcBounds::parse(&settings.gc));
+    }
+    if !settings.gc_window.is_empty() {
+        constraints.gc_window = Some(constraints::GcWindow::parse(&settings.gc_window));
+    }
+    constraints.gc_mode = constraints::ConstraintMode::parse(&settings.gc_mode);
+    constraints.gc_penalty = settings.gc_penalty;
+    constraints.repeat_length = settings.repeat_length;
+    constraints.repeat_mode = constraints::ConstraintMode::parse(&settings.repeat_mode);
+    constraints.repeat_penalty = settings.repeat_penalty;
+
+    let mut objectives = objectives::Objectives::new(
+        c![objectives::Objective::parse(i), for i in &settings.objectives],
+        settings.objective_weights.clone(),
+        match settings.objective_mode.as_str() {
+            "weighted" => false,
+            "pareto" => true,
+            _ => panic!("Objective mode must be weighted or pareto"),
+        },
     );
+    objectives.cai = c![
+        objectives::CodonWeights::cai(i, &amino_singular_map),
+        for i in &host_usages];
+    objectives.tai = c![
+        i.trna.as_ref().map(|t| objectives::CodonWeights::tai(t, &codon_singular_map, &codon_amino_map)),
+        for i in &settings.hosts];
+    objectives.cpb = c![
+        objectives::CodonPairScores::new(i, &amino_singular_map),
+        for i in &host_usages];
+    objectives.five_prime = Some(objectives::FivePrime::new(
+        &settings.utr,
+        settings.mfe_window.0,
+        settings.mfe_window.1,
+        &singular_codon_map,
+    ));
+    objectives.thresholds = c![objectives::Threshold::parse(i), for i in &settings.thresholds];
+    objectives.threshold_mode = match constraints::ConstraintMode::parse(&settings.threshold_mode) {
+        constraints::ConstraintMode::Repair => panic!("Threshold mode must be penalty or reject"),
+        t => t,
+    };
+    objectives.threshold_penalty = settings.threshold_penalty;
+
+    let host_names = settings.host_names();
+    let mut trace = settings
+        .trace
+        .as_ref()
+        .map(|t| trace::Trace::new(t, &host_names));
+    info!("Random seed: {}", settings.random_seed);
+
+
+    // Optimise each protein in turn.
+    let mut results: Vec<ProteinResult> = vec![];
+    for (idx, ((pro_id, pro_seq), protein_seeds)) in proteins.iter().zip(seeds.iter()).enumerate() {
+        info!("Optimising {} ({} of {})", pro_id, results.len() + 1, proteins.len());
+        let protein_start = Instant::now();
+        let condensed = c![&i[idx], for i in &host_condensed];
+        info!("Creating match indexes");
+        let indexes = c![matching::MatchIndex::new(i), for i in &condensed];
+
+        // Profile the native CDS against the source organism, to harmonise with it.
+        objectives.harmony = match (&natives[idx], &source) {
+            (Some(native), Some((source_usage, source_condensed))) => {
+                let source_index = matching::MatchIndex::new(&source_condensed[idx]);
+                Some(objectives::Harmony::new(
+                    harmony_mode,
+                    &ealgorithm::ars_profile(native, &source_index),
+                    native,
+                    &objectives::CodonWeights::cai(source_usage, &amino_singular_map),
+                ))
+            }
+            _ => None,
+        };
+
+        // Run the optimiser
+        let params = ealgorithm::Parameters {
+            n_cross: settings.crossovers,
+            n_mut: settings.mutations,
+            n_gen: settings.generations,
+            n_gen_start: settings.gen_start,
+            method: &settings.method,
+            weights: &weights,
+            seeds: protein_seeds,
+            seed_fraction: settings.seed_fraction,
+            top: settings.top,
+            min_distance: settings.min_distance,
+            random_seed: settings.random_seed,
+            stall: settings.stall,
+            cache_size: settings.cache_size,
+            dedup: settings.dedup,
+            islands: settings.islands,
+            migration_interval: settings.migration_interval,
+            migrants: settings.migrants,
+            topology: ealgorithm::Topology::parse(&settings.topology),
+            diversity: ealgorithm::Diversity::parse(&settings.diversity),
+            sharing_radius: settings.sharing_radius,
+            restart_diversity: settings.restart_diversity,
+            constraints: &constraints,
+            objectives: &objectives,
+            template: templates[idx].as_deref(),
+            locked: &locks[idx],
+        };
+        if let Some(t) = trace.as_mut() {
+            t.set_protein(pro_id);
+        }
+        let problem = optimisers::Problem {
+            protein: pro_seq,
+            indexes: &indexes,
+            amino_singular: &amino_singular_map,
+            alternatives: &alternatives,
+            params: &params,
+        };
+        let mut optimiser = optimisers::parse(
+            &settings.optimiser,
+            settings.temperature,
+            optimisers::Cooling::parse(&settings.cooling, settings.cooling_rate),
+            settings.learning_rate,
+            settings.random_seed,
+        );
+        let outcome = optimisers::run(optimiser.as_mut(), &problem, trace.as_mut());
+        if outcome.designs.is_empty() {
+            panic!("Every design for {} was rejected by the thresholds or constraints", pro_id);
+        }
+
+        // Find how far the designs could be improved against each host, keeping the template's
+        // codons at locked positions.
+        let ars_bounds = if settings.bound {
+            let codes = c![match (&templates[idx], locks[idx][k]) {
+                (Some(t), true) => vec![t.as_bytes()[k]],
+                _ => c![j.as_bytes()[0], for j in &amino_singular_map[&pro_seq[k..=k]]],
+            }, for k in 0..pro_seq.len()];
+            let found = c![
+                bounds::ars_bound(condensed[k], &indexes[k], &codes, settings.bound_states),
+                for k in 0..indexes.len()];
+            for (h, (name, bound)) in host_names.iter().zip(found.iter()).enumerate() {
+                // The design with the highest ARS against this host need not be the top design.
+                let best = outcome.designs.iter().map(|i| i.host_scores[h]).fold(f64::NEG_INFINITY, f64::max);
+                info!(
+                    "{} ARS bound for {}: {} ({}), {} above the best design for that host",
+                    if bound.exact { "Exact" } else { "Upper" },
+                    name,
+                    bound.ars,
+                    pro_id,
+                    bound.ars - best
+                );
+            }
+            Some(found)
+        } else {
+            None
+        };
+
+        let mut designs: Vec<DesignResult> = vec![];
+        for (rank, design) in outcome.designs.into_iter().enumerate() {
+            let nucleotides = constraints.nucleotides(&design.sequence);
+            let profiles = c![ealgorithm::ars_profile(&design.sequence, i), for i in &indexes];
+            let result = DesignResult {
+                sites: constraints.find_sites(&nucleotides),
+                motifs: constraints.find_motifs(&nucleotides),
+                gc: constraints::gc_content(&nucleotides),
+                gc_violations: constraints.find_gc(&nucleotides),
+                repeats: constraints.find_repeats(&nucleotides),
+                cai: c![i.score(&design.sequence), for i in &objectives.cai],
+                tai: c![i.as_ref().map(|t| t.score(&design.sequence)), for i in &objectives.tai],
+                cpb: c![i.score(&design.sequence), for i in &objectives.cpb],
+                mfe: objectives.five_prime.as_ref().unwrap().mfe(&design.sequence),
+                harmony: objectives.harmony.as_ref().map(|_| {
+                    objectives.host_scores(
+                        objectives::Objective::Harmony,
+                        &design.sequence,
+                        &design.host_scores,
+                        &profiles,
+                    )
+                }),
+                shortfall: objectives.shortfall(&design.sequence, &design.host_scores, &profiles),
+                nucleotides,
+                design,
+            };
+
+            let n_sites = constraints.count_sites(&result.design.sequence);
+            if n_sites > 0 {
+                warn!("Design {} for {} contains {} forbidden sites", rank + 1, pro_id, n_sites);
+            }
+            let n_motifs = constraints.count_motifs(&result.design.sequence);
+            if n_motifs > 0 {
+                warn!("Design {} for {} has {} motif violations", rank + 1, pro_id, n_motifs);
+            }
+            if !result.gc_violations.is_empty() {
+                warn!("Design {} for {} has {} GC content violations", rank + 1, pro_id, result.gc_violations.len());
+            }
+            if !result.repeats.is_empty() {
+                warn!("Design {} for {} contains {} repeats", rank + 1, pro_id, result.repeats.len());
+            }
+            if result.shortfall > 0.0 {
+                warn!("Design {} for {} falls short of thresholds by {}", rank + 1, pro_id, result.shortfall);
+            }
+            designs.push(result);
+        }
+        results.push(ProteinResult {
+            id: pro_id.clone(),
+            length: pro_seq.len(),
+            designs,
+            locked: c![k + 1, for k in 0..pro_seq.len(), if locks[idx][k]],
+            generations: outcome.generations,
+            stop_reason: outcome.stop_reason,
+            cache_hit_rate: outcome.cache_hit_rate,
+            restarts: outcome.restarts,
+            bounds: ars_bounds,
+            duration: protein_start.elapsed(),
+        });
+    }
+    if let Some(t) = trace.as_mut() {
+        t.finish();
+    }
 
-    let encoded = encoding_to_nuc(&result_seq, &singular_codon_map);
     let duration = start.elapsed();
-    write_to_fasta(outfile, &encoded, result_fitness, &duration).unwrap();
-    info!("Chimera evolve algorithm completed -- thank you for flying Air ICOS! 🚀")
+    write_to_fasta(&settings.outfile, &results, &host_names).unwrap();
+
+    if let Some(t) = &settings.summary {
+        info!("Writing summary table to {}", t);
+        let rows = results
+            .iter()
+            .flat_map(|i| {
+                i.designs
+                    .iter()
+                    .enumerate()
+                    .map(move |(rank, design)| report::SummaryRow {
+                        protein: &i.id,
+                        length: i.length,
+                        design: rank + 1,
+                        score: design.design.fitness,
+                        host_scores: &design.design.host_scores,
+                        cai: &design.cai,
+                        tai: &design.tai,
+                        cpb: &design.cpb,
+                        mfe: design.mfe,
+                        sites: design.sites.len(),
+                        motifs: design.motifs.len(),
+                        gc: design.gc,
+                        gc_violations: design.gc_violations.len(),
+                        repeats: design.repeats.len(),
+                        generations: i.generations,
+                        stop_reason: i.stop_reason.as_str(),
+                    })
+            })
+            .collect::<Vec<report::SummaryRow>>();
+        report::write_summary(t, &host_names, &rows);
+    }
+
+    if let Some(t) = &settings.report {
+        info!("Writing run report to {}", t);
+        let protein_reports = c![json!({
+            "protein_id": i.id,
+            "designs": c![json!({
+                "sequence": j.nucleotides,
+                "score": j.design.fitness,
+                "host_scores": host_names
+                    .iter()
+                    .zip(j.design.host_scores.iter())
+                    .map(|(name, score)| (name.clone(), json!(score)))
+                    .collect::<serde_json::Map<String, serde_json::Value>>(),
+                "cai": host_names
+                    .iter()
+                    .zip(j.cai.iter())
+                    .map(|(name, score)| (name.clone(), json!(score)))
+                    .collect::<serde_json::Map<String, serde_json::Value>>(),
+                "tai": host_names
+                    .iter()
+                    .zip(j.tai.iter())
+                    .filter_map(|(name, score)| score.map(|t| (name.clone(), json!(t))))
+                    .collect::<serde_json::Map<String, serde_json::Value>>(),
+                "cpb": host_names
+                    .iter()
+                    .zip(j.cpb.iter())
+                    .map(|(name, score)| (name.clone(), json!(score)))
+                    .collect::<serde_json::Map<String, serde_json::Value>>(),
+                "objectives": objectives
+                    .kinds
+                    .iter()
+                    .zip(j.design.objective_scores.iter())
+                    .map(|(name, score)| (name.as_str().to_string(), json!(score)))
+                    .collect::<serde_json::Map<String, serde_json::Value>>(),
+                "harmony": j.harmony.as_ref().map(|k| host_names
+                    .iter()
+                    .zip(k.iter())
+                    .map(|(name, score)| (name.clone(), json!(score)))
+                    .collect::<serde_json::Map<String, serde_json::Value>>()),
+                "five_prime": {
+                    "window": objectives.five_prime.as_ref().unwrap().window(&j.design.sequence),
+                    "mfe": j.mfe,
+                },
+                "thresholds": {
+                    "mode": objectives.threshold_mode.as_str(),
+                    "checked": c![json!({"objective": k.objective.as_str(), "min": k.min}), for k in &objectives.thresholds],
+                    "shortfall": j.shortfall,
+                },
+                "restriction_sites": {
+                    "mode": constraints.site_mode.as_str(),
+                    "checked": c![json!({"name": k.name, "sequence": k.sequence}), for k in &constraints.sites],
+                    "found": c![json!({
+                        "name": k.name,
+                        "position": k.position,
+                        "strand": if k.reverse { "-" } else { "+" },
+                    }), for k in &j.sites],
+                },
+                "motifs": {
+                    "mode": constraints.motif_mode.as_str(),
+                    "checked": c![k.name(), for k in &constraints.motifs],
+                    "violations": c![json!({
+                        "name": k.name,
+                        "position": k.position,
+                        "length": k.length,
+                    }), for k in &j.motifs],
+                },
+                "gc": {
+                    "mode": constraints.gc_mode.as_str(),
+                    "content": j.gc,
+                    "bounds": constraints.gc.map(|k| vec![k.min, k.max]),
+                    "window": constraints.gc_window.map(|k| json!({
+                        "size": k.size,
+                        "bounds": vec![k.bounds.min, k.bounds.max],
+                    })),
+                    "violations": c![json!({
+                        "name": k.name,
+                        "position": k.position,
+                        "length": k.length,
+                        "gc": constraints::gc_content(&j.nucleotides[k.position..k.position + k.length]),
+                    }), for k in &j.gc_violations],
+                },
+                "repeats": {
+                    "mode": constraints.repeat_mode.as_str(),
+                    "min_length": constraints.repeat_length,
+                    "found": c![json!({
+                        "type": k.kind.as_str(),
+                        "first": k.first,
+                        "second": k.second,
+                        "length": k.length,
+                    }), for k in &j.repeats],
+                },
+                // A design is ready for synthesis if it breaks none of the constraints.
+                "synthesis_ready": j.sites.is_empty()
+                    && j.motifs.is_empty()
+                    && j.gc_violations.is_empty()
+                    && j.repeats.is_empty(),
+            }), for j in &i.designs],
+            "locked_codons": i.locked,
+            "harmony_mode": harmony_mode.as_str(),
+            "generations_run": i.generations,
+            "stop_reason": i.stop_reason.as_str(),
+            "cache_hit_rate": i.cache_hit_rate,
+            "restarts": i.restarts,
+            // The best ARS against each host, whether it is exact or an upper bound, and how far
+            // the best design falls below it.
+            "ars_bounds": i.bounds.as_ref().map(|k| host_names
+                .iter()
+                .zip(k.iter())
+                .enumerate()
+                .map(|(h, (name, bound))| (name.clone(), json!({
+                    "ars": bound.ars,
+                    "exact": bound.exact,
+                    "gap": bound.ars - i.designs.iter().map(|d| d.design.host_scores[h]).fold(f64::NEG_INFINITY, f64::max),
+                })))
+                .collect::<serde_json::Map<String, serde_json::Value>>()),
+            "duration_seconds": i.duration.as_secs_f64(),
+        }), for i in &results];
+        let run_report = json!({
+            "version": crate_version!(),
+            "inputs": {
+                "protein": report::input(&settings.protein),
+                "hosts": c![report::input(&i.cds), for i in &settings.hosts],
+                "seeds": c![report::input(i), for i in &settings.seeds],
+                "trna": c![report::input(i), for i in settings.hosts.iter().filter_map(|j| j.trna.as_ref())],
+                "template": settings.template.as_ref().map(|i| report::input(i)),
+                "native": settings.native.as_ref().map(|i| report::input(i)),
+                "source": settings.source.as_ref().map(|i| report::input(i)),
+            },
+            "hosts": host_names,
+            "translation_table": settings.table,
+            "config": settings.to_json(),
+            "proteins": protein_reports,
+            "duration_seconds": duration.as_secs_f64(),
+        });
+        report::write_report(t, &run_report);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // Writes a FASTA of records to a file in the temporary directory, returning its path.
+    fn write_fasta(name: &str, records: &[(&str, &str)]) -> String {
+        let path = std::env::temp_dir().join(name);
+        let mut file = File::create(&path).unwrap();
+        for (id, seq) in records {
+            writeln!(file, ">{}\n{}", id, seq).unwrap();
+        }
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_parse_seeds() {
+        let (codon_singular_map, _, codon_amino_map, _, _, _) =
+            translation_tables::generate_maps(translation_tables::tt11());
+        let encode = |i: &str| c![*codon_singular_map.get(&i[j..j + 3]).unwrap(), for j in (0..i.len()).step_by(3)].concat();
+        let proteins = c![(i.0.to_string(), i.1.to_string()), for i in [("a", "MK"), ("b", "MKE"), ("c", "MKE*")]];
+        // Seeds are grouped by the protein they encode, in order, so the first of each is the one
+        // used as a template or native CDS. A terminal stop codon is dropped unless the protein
+        // includes one.
+        let path = write_fasta(
+            "chimera_evolve_test_seeds.fasta",
+            &[("s1", "ATGAAAGAA"), ("s2", "atgaagtaa"), ("s3", "ATGAAGGAGTAG"), ("s4", "ATGAAA")],
+        );
+        let seeds = parse_seeds("Seed", &path, &proteins, &codon_singular_map, &codon_amino_map);
+        fs::remove_file(path).unwrap();
+        assert_eq!(seeds[0], vec![encode("ATGAAG"), encode("ATGAAA")]);
+        assert_eq!(seeds[1], vec![encode("ATGAAAGAA"), encode("ATGAAGGAG")]);
+        assert_eq!(seeds[2], vec![encode("ATGAAGGAGTAG")]);
+    }
+    #[test]
+    #[should_panic(expected = "Seed s2 does not encode the given protein.")]
+    fn test_parse_seeds_mismatch() {
+        let (codon_singular_map, _, codon_amino_map, _, _, _) =
+            translation_tables::generate_maps(translation_tables::tt11());
+        let proteins = vec![("a".to_string(), "MK".to_string())];
+        let path = write_fasta("chimera_evolve_test_mismatch.fasta", &[("s1", "ATGAAA"), ("s2", "ATGGAA")]);
+        let result = std::panic::catch_unwind(|| parse_seeds("Seed", &path, &proteins, &codon_singular_map, &codon_amino_map));
+        fs::remove_file(path).unwrap();
+        std::panic::resume_unwind(result.unwrap_err());
+    }
+    #[test]
+    #[should_panic(expected = "Native CDS n1 has a length that is not a multiple of three.")]
+    fn test_parse_natives_length() {
+        let (codon_singular_map, _, codon_amino_map, _, _, _) =
+            translation_tables::generate_maps(translation_tables::tt11());
+        let proteins = vec![("a".to_string(), "MK".to_string())];
+        let path = write_fasta("chimera_evolve_test_native_length.fasta", &[("n1", "ATGAA")]);
+        let result =
+            std::panic::catch_unwind(|| parse_seeds("Native CDS", &path, &proteins, &codon_singular_map, &codon_amino_map));
+        fs::remove_file(path).unwrap();
+        std::panic::resume_unwind(result.unwrap_err());
+    }
+    #[test]
+    fn test_two_proteins() {
+        // Each record of the protein FASTA gets its own designs and summary row, and its own seed,
+        // template and native CDS, whatever order they are given in.
+        let name = |i: &str| format!("chimera_evolve_test_run_{}", i);
+        let path = |i: &str| std::env::temp_dir().join(name(i)).to_str().unwrap().to_string();
+        let (p1, p2) = ("M".to_string() + &"L".repeat(12), "M".to_string() + &"L".repeat(15));
+        let protein = write_fasta(&name("protein.fasta"), &[("p1", &p1), ("p2", &p2)]);
+        let (seed1, seed2) = ("ATG".to_string() + &"CTG".repeat(12), "ATG".to_string() + &"TTA".repeat(15));
+        let seeds = write_fasta(&name("seeds.fasta"), &[("s2", &seed2), ("s1", &seed1)]);
+        let (template1, template2) = ("ATG".to_string() + &"CTT".repeat(12), "ATG".to_string() + &"CTC".repeat(15));
+        let template = write_fasta(&name("template.fasta"), &[("t2", &template2), ("t1", &template1)]);
+        let host = write_fasta(&name("host.fasta"), &[("h1", "ATGCTGCTGTTACTTCTCCTA"), ("h2", "ATGTTGCTGCTGCTG")]);
+        let outputs = [path("out.fasta"), path("summary.tsv"), path("report.json")];
+        // One generation of seeded candidates.
+        let args = [
+            "chimera-evolve", &protein, &host, "-g", "1", "-m", "4", "-c", "3", "-n", "1", "-f", "1", "-S", &seeds,
+            "--template", &template, "--lock", "2", "--native", &seeds, "--source", &host,
+            "-o", &outputs[0], "-u", &outputs[1], "-R", &outputs[2],
+        ];
+        let yaml = load_yaml!("cli.yml");
+        let matches = App::from_yaml(yaml).get_matches_from(args);
+        run(&config::Settings::from_matches(&matches));
+
+        // The designs are the seeds, mutated in no more than five codons, with the second codon
+        // kept from each template.
+        let designs = c![i.unwrap(), for i in bio::io::fasta::Reader::from_file(&outputs[0]).unwrap().records()];
+        assert_eq!(c![i.id(), for i in &designs], vec!["p1_1", "p2_1"]);
+        for (design, (seed, template)) in designs.iter().zip([(&seed1, &template1), (&seed2, &template2)]) {
+            let sequence = std::str::from_utf8(design.seq()).unwrap();
+            assert_eq!(sequence.len(), seed.len());
+            assert_eq!(&sequence[3..6], &template[3..6]);
+            let differences = (0..seed.len()).step_by(3).filter(|i| sequence[*i..*i + 3] != seed[*i..*i + 3]).count();
+            assert!(differences <= 6);
+        }
+
+        let summary = fs::read_to_string(&outputs[1]).unwrap();
+        let rows = c![i.split('\t').take(3).collect::<Vec<&str>>(), for i in summary.lines().skip(1)];
+        assert_eq!(rows, vec![vec!["p1", "13", "1"], vec!["p2", "16", "1"]]);
+
+        // Each protein is harmonised with its own native CDS.
+        let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(&outputs[2]).unwrap()).unwrap();
+        for (i, id) in ["p1", "p2"].iter().enumerate() {
+            let protein = &report["proteins"][i];
+            assert_eq!(protein["protein_id"], *id);
+            assert!(protein["designs"][0]["harmony"].is_object());
+        }
+        for i in [protein, seeds, template, host].iter().chain(outputs.iter()) {
+            fs::remove_file(i).unwrap();
+        }
+    }
+    #[test]
+    #[should_panic(expected = "Every design for p1 was rejected by the thresholds or constraints")]
+    fn test_all_rejected() {
+        // No coding sequence for the protein is 90% G or C, so no designs are written.
+        let name = |i: &str| format!("chimera_evolve_test_rejected_{}", i);
+        let protein = write_fasta(&name("protein.fasta"), &[("p1", "MLLL")]);
+        let host = write_fasta(&name("host.fasta"), &[("h1", "ATGCTGCTGTTACTTCTCCTA")]);
+        let output = std::env::temp_dir().join(name("out.fasta")).to_str().unwrap().to_string();
+        let args = [
+            "chimera-evolve", &protein, &host, "-g", "1", "-m", "4", "-c", "3", "--gc", "0.9,1.0", "--gc_mode", "reject",
+            "-o", &output,
+        ];
+        let yaml = load_yaml!("cli.yml");
+        let matches = App::from_yaml(yaml).get_matches_from(args);
+        let result = std::panic::catch_unwind(|| run(&config::Settings::from_matches(&matches)));
+        for i in [&protein, &host] {
+            fs::remove_file(i).unwrap();
+        }
+        assert!(!std::path::Path::new(&output).exists());
+        std::panic::resume_unwind(result.unwrap_err());
+    }
 }
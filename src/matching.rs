@@ -0,0 +1,243 @@
+use suffix::SuffixTable;
+
+/// Finds, for each position of a string, the length of the longest substring starting there that
+/// occurs in a reference text.
+pub trait MatchingStatistics {
+    fn match_lengths(&self, string: &str) -> Vec<usize>;
+}
+
+impl<'s, 't> MatchingStatistics for SuffixTable<'s, 't> {
+    fn match_lengths(&self, string: &str) -> Vec<usize> {
+        // Searches the suffix table for every prefix at every position.
+        (0..string.len())
+            .map(|idx| {
+                let substring = &string[idx..];
+                let mut counter = 0;
+                for s_idx in 0..substring.len() {
+                    if self.contains(&substring[..=s_idx]) {
+                        counter += 1;
+                    } else {
+                        break;
+                    }
+                }
+                counter
+            })
+            .collect()
+    }
+}
+
+/// A suffix automaton of a reversed reference text, from which the matching statistics of a
+/// string are found in a single right-to-left pass.
+pub struct MatchIndex {
+    // For each state, its suffix link, the length of its longest string and its transitions.
+    link: Vec<u32>,
+    len: Vec<u32>,
+    next: Vec<Vec<(u8, u32)>>,
+}
+
+impl MatchIndex {
+    pub fn new(text: &str) -> MatchIndex {
+        let mut index = MatchIndex {
+            link: vec![0],
+            len: vec![0],
+            next: vec![vec![]],
+        };
+        let mut last = 0;
+        for c in text.bytes().rev() {
+            last = index.extend(last, c);
+        }
+        index
+    }
+
+    fn transition(&self, state: usize, c: u8) -> Option<usize> {
+        self.next[state].iter().find(|i| i.0 == c).map(|i| i.1 as usize)
+    }
+
+    fn set_transition(&mut self, state: usize, c: u8, target: usize) {
+        match self.next[state].iter_mut().find(|i| i.0 == c) {
+            Some(i) => i.1 = target as u32,
+            None => self.next[state].push((c, target as u32)),
+        }
+    }
+
+    fn extend(&mut self, last: usize, c: u8) -> usize {
+        // Adds one character to the automaton, returning the state for the whole text so far.
+        let current = self.len.len();
+        self.len.push(self.len[last] + 1);
+        self.link.push(0);
+        self.next.push(vec![]);
+
+        let mut p = Some(last);
+        while let Some(i) = p {
+            if self.transition(i, c).is_some() {
+                break;
+            }
+            self.set_transition(i, c, current);
+            p = if i == 0 { None } else { Some(self.link[i] as usize) };
+        }
+        let p = match p {
+            Some(i) => i,
+            None => return current,
+        };
+
+        let q = self.transition(p, c).unwrap();
+        if self.len[p] + 1 == self.len[q] {
+            self.link[current] = q as u32;
+            return current;
+        }
+
+        // Split q, so that the state reached from p keeps its lengths consistent.
+        let clone = self.len.len();
+        self.len.push(self.len[p] + 1);
+        self.link.push(self.link[q]);
+        self.next.push(self.next[q].clone());
+        let mut p = Some(p);
+        while let Some(i) = p {
+            if self.transition(i, c) != Some(q) {
+                break;
+            }
+            self.set_transition(i, c, clone);
+            p = if i == 0 { None } else { Some(self.link[i] as usize) };
+        }
+        self.link[q] = clone as u32;
+        self.link[current] = clone as u32;
+        current
+    }
+
+    // Moves a match one character to the left, shortening it until it can be extended, and returns
+    // the new state and match length.
+    pub fn step(&self, mut state: usize, mut length: usize, c: u8) -> (usize, usize) {
+        loop {
+            if let Some(t) = self.transition(state, c) {
+                return (t, length + 1);
+            }
+            if state == 0 {
+                return (0, 0);
+            }
+            state = self.link[state] as usize;
+            length = self.len[state] as usize;
+        }
+    }
+
+    // Finds the matching statistics of a string, keeping the automaton state at each position.
+    pub fn matches(&self, string: &[u8]) -> Matches {
+        let mut matches = Matches {
+            states: vec![0; string.len()],
+            lengths: vec![0; string.len()],
+        };
+        self.update(string, &mut matches, 0, string.len());
+        matches
+    }
+
+    // Updates the matching statistics of a string whose characters from start to end (exclusive)
+    // have changed. Reading the string backwards through the reversed text's automaton, the
+    // longest match ending at each character is the longest match starting there in the forward
+    // direction, so positions to the right are unaffected, and the scan stops once it rejoins the
+    // previous states to the left.
+    pub fn update(&self, string: &[u8], matches: &mut Matches, start: usize, end: usize) {
+        let (mut state, mut length) = match matches.states.get(end) {
+            Some(t) => (*t as usize, matches.lengths[end] as usize),
+            None => (0, 0),
+        };
+        for idx in (0..end).rev() {
+            let (s, l) = self.step(state, length, string[idx]);
+            state = s;
+            length = l;
+            if idx < start && matches.states[idx] as usize == state && matches.lengths[idx] as usize == length {
+                break;
+            }
+            matches.states[idx] = state as u32;
+            matches.lengths[idx] = length as u32;
+        }
+    }
+}
+
+impl MatchingStatistics for MatchIndex {
+    fn match_lengths(&self, string: &str) -> Vec<usize> {
+        c![*i as usize, for i in self.matches(string.as_bytes()).lengths()]
+    }
+}
+
+/// The matching statistics of a string against a match index, with the automaton state at each
+/// position so that they can be updated when a few characters change.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Matches {
+    states: Vec<u32>,
+    lengths: Vec<u32>,
+}
+
+impl Matches {
+    pub fn lengths(&self) -> &[u32] {
+        &self.lengths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_match_lengths() {
+        let index = MatchIndex::new("ACTG");
+        assert_eq!(index.match_lengths("ACTG"), vec![4, 3, 2, 1]);
+        assert_eq!(index.match_lengths("ACAC"), vec![2, 1, 2, 1]);
+        assert_eq!(index.match_lengths("XA"), vec![0, 1]);
+
+        // Matches never run across the separators between records.
+        let text = "abcab|bca|cc|";
+        let (index, table) = (MatchIndex::new(text), SuffixTable::new(text));
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..200 {
+            let string = c![["a", "b", "c"][rng.gen_range(0, 3)], for _i in 0..12].concat();
+            assert_eq!(index.match_lengths(&string), table.match_lengths(&string));
+        }
+    }
+    #[test]
+    fn test_update_matches() {
+        let index = MatchIndex::new("abcab|bca|cc|");
+        let mut rng = StdRng::seed_from_u64(2);
+        let letters = ["a", "b", "c"];
+        let mut string = c![letters[rng.gen_range(0, 3)], for _i in 0..30].concat().into_bytes();
+        let mut matches = index.matches(&string);
+        for _ in 0..200 {
+            // Change a short run of characters, and update the statistics to match.
+            let start = rng.gen_range(0, 30);
+            let end = rng.gen_range(start + 1, 31.min(start + 4));
+            let run = c![letters[rng.gen_range(0, 3)], for _i in start..end].concat();
+            string.splice(start..end, run.bytes());
+            index.update(&string, &mut matches, start, end);
+            assert_eq!(matches, index.matches(&string));
+        }
+    }
+    #[test]
+    fn test_condensed_host() {
+        // Compares the match index with the suffix table search on a text made like a condensed
+        // host, of copies of the string with a few characters changed, before and after mutations.
+        let mut rng = StdRng::seed_from_u64(3);
+        let letters = c![i.to_string(), for i in "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz012345678".chars()];
+        let mut random = |n: usize| c![letters[rng.gen_range(0, letters.len())].as_str(), for _i in 0..n].concat();
+        let mut string = random(300).into_bytes();
+        let text = c![{
+            let mut copy = string.clone();
+            for _ in 0..15 {
+                copy[rng.gen_range(0, 300)] = letters[rng.gen_range(0, letters.len())].as_bytes()[0];
+            }
+            String::from_utf8(copy).unwrap() + "|"
+        }, for _i in 0..100].concat();
+
+        let table = SuffixTable::new(text.as_str());
+        let index = MatchIndex::new(&text);
+        let mut matches = index.matches(&string);
+        assert_eq!(c![*i as usize, for i in matches.lengths()], table.match_lengths(std::str::from_utf8(&string).unwrap()));
+
+        // Rescore after changing a few characters, as after a mutation.
+        for idx in 0..100 {
+            let pos = (idx * 37) % string.len();
+            string[pos] = letters[idx % letters.len()].as_bytes()[0];
+            index.update(&string, &mut matches, pos, pos + 1);
+        }
+        assert_eq!(c![*i as usize, for i in matches.lengths()], table.match_lengths(std::str::from_utf8(&string).unwrap()));
+    }
+}
@@ -0,0 +1,603 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+
+use crate::constraints::ConstraintMode;
+use crate::folding;
+
+// Selective penalties for wobble pairings between the first anticodon base and the third codon
+// base, from dos Reis et al. (2004). Watson-Crick pairings have no penalty.
+const S_GU: f64 = 0.41;
+const S_IC: f64 = 0.28;
+const S_IA: f64 = 0.9999;
+const S_UG: f64 = 0.68;
+
+fn complement(base: char) -> char {
+    match base {
+        'A' => 'T',
+        'C' => 'G',
+        'G' => 'C',
+        'T' => 'A',
+        _ => base,
+    }
+}
+
+/// Counts of the codons and pairs of adjacent codons in a host's CDSs, which can be added to a CDS
+/// at a time as they are read.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CodonUsage {
+    codons: HashMap<char, f64>,
+    codon_pairs: HashMap<(char, char), f64>,
+}
+
+impl CodonUsage {
+    // Adds the codons of one encoded CDS. Pairs are only counted within each CDS.
+    pub fn add(&mut self, encoded_cds: &str) {
+        let cds = encoded_cds.chars().collect::<Vec<char>>();
+        for i in &cds {
+            *self.codons.entry(*i).or_insert(0.0) += 1.0;
+        }
+        for i in cds.windows(2) {
+            *self.codon_pairs.entry((i[0], i[1])).or_insert(0.0) += 1.0;
+        }
+    }
+}
+
+/// Relative adaptiveness of each codon, keyed by its encoding. Codons without a weight are not scored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodonWeights {
+    log_weights: HashMap<char, f64>,
+}
+
+impl CodonWeights {
+    // Weights each codon by its usage in the host CDSs relative to the most used synonymous codon.
+    // Stop codons and amino acids with a single codon are left out.
+    pub fn cai(usage: &CodonUsage, amino_singular_map: &HashMap<&str, Vec<&str>>) -> CodonWeights {
+        let counts = &usage.codons;
+        let mut log_weights: HashMap<char, f64> = HashMap::new();
+        for (amino, codes) in amino_singular_map {
+            if *amino == "*" || codes.len() < 2 {
+                continue;
+            }
+            // Unused codons count as half a use, so no weight is zero.
+            let usage = c![
+                (i.chars().next().unwrap(), counts.get(&i.chars().next().unwrap()).cloned().unwrap_or(0.0).max(0.5)),
+                for i in codes];
+            let max = usage.iter().map(|i| i.1).fold(f64::NAN, f64::max);
+            for (code, count) in usage {
+                log_weights.insert(code, (count / max).ln());
+            }
+        }
+        CodonWeights { log_weights }
+    }
+
+    // Weights each codon by how well the host's tRNA genes decode it, as in the tRNA adaptation index.
+    // The file gives one anticodon (5' to 3') and its gene copy number per line.
+    pub fn tai(
+        path: &str,
+        codon_singular_map: &HashMap<&str, &str>,
+        codon_amino_map: &HashMap<&str, &str>,
+    ) -> CodonWeights {
+        let text = fs::read_to_string(path).unwrap();
+        let mut copies: HashMap<String, f64> = HashMap::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = line.split_whitespace().collect::<Vec<&str>>();
+            if fields.len() != 2 {
+                panic!("Invalid line in tRNA table {}: {}", path, line);
+            }
+            let anticodon = fields[0].to_uppercase().replace('U', "T");
+            let count = match fields[1].parse::<f64>() {
+                Ok(t) => t,
+                Err(_) => panic!("Invalid tRNA gene copy number in {}: {}", path, line),
+            };
+            *copies.entry(anticodon).or_insert(0.0) += count;
+        }
+
+        // Sum the copies of the tRNAs that decode each sense codon, less the wobble penalties.
+        let mut absolute: Vec<(char, &str, f64)> = vec![];
+        for (codon, amino) in codon_amino_map {
+            if *amino == "*" {
+                continue;
+            }
+            let bases = codon.chars().collect::<Vec<char>>();
+            let stem = format!("{}{}", complement(bases[1]), complement(bases[0]));
+            let copies_of = |wobble: char| copies.get(&format!("{}{}", wobble, stem)).cloned().unwrap_or(0.0);
+            let w = match bases[2] {
+                'T' => copies_of('A') + (1.0 - S_GU) * copies_of('G'),
+                'C' => copies_of('G') + (1.0 - S_IC) * copies_of('A'),
+                'A' => copies_of('T') + (1.0 - S_IA) * copies_of('A'),
+                _ => copies_of('C') + (1.0 - S_UG) * copies_of('T'),
+            };
+            let code = codon_singular_map.get(codon).unwrap().chars().next().unwrap();
+            absolute.push((code, amino, w));
+        }
+        let max = absolute.iter().map(|i| i.2).fold(f64::NAN, f64::max);
+        if max.is_nan() || max <= 0.0 {
+            panic!("tRNA table {} does not decode any codons", path);
+        }
+
+        // Codons no tRNA decodes take the geometric mean of the other weights.
+        let decoded = c![(i.2 / max).ln(), for i in &absolute, if i.2 > 0.0];
+        let mean = decoded.iter().sum::<f64>() / decoded.len() as f64;
+        // Methionine is left out, as its codon cannot be changed.
+        let log_weights = c! {i.0 => if i.2 > 0.0 { (i.2 / max).ln() } else { mean },
+            for i in absolute, if i.1 != "M"};
+        CodonWeights { log_weights }
+    }
+
+    // The relative adaptiveness of a codon, if it is scored.
+    pub fn weight(&self, code: char) -> Option<f64> {
+        self.log_weights.get(&code).map(|i| i.exp())
+    }
+
+    // The geometric mean of the weights of the codons in an encoded sequence.
+    pub fn score(&self, encoded: &str) -> f64 {
+        let weights = c![*i, for i in encoded.chars().filter_map(|j| self.log_weights.get(&j))];
+        if weights.is_empty() {
+            return 0.0;
+        }
+        (weights.iter().sum::<f64>() / weights.len() as f64).exp()
+    }
+}
+
+/// Codon pair scores for a host, keyed by the encodings of each pair of codons.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodonPairScores {
+    scores: HashMap<(char, char), f64>,
+}
+
+impl CodonPairScores {
+    // Scores each codon pair by the log ratio of its count in the host CDSs to the count expected
+    // from the usage of its codons and amino acids, as in Coleman et al. (2008).
+    pub fn new(usage: &CodonUsage, amino_singular_map: &HashMap<&str, Vec<&str>>) -> CodonPairScores {
+        let amino_of = amino_singular_map
+            .iter()
+            .flat_map(|(amino, codes)| codes.iter().map(move |i| (i.chars().next().unwrap(), *amino)))
+            .collect::<HashMap<char, &str>>();
+        let (codons, codon_pairs) = (&usage.codons, &usage.codon_pairs);
+        // Count the amino acids, and pairs of them, from their codons.
+        let mut aminos: HashMap<&str, f64> = HashMap::new();
+        let mut amino_pairs: HashMap<(&str, &str), f64> = HashMap::new();
+        for (i, n) in codons {
+            *aminos.entry(amino_of[i]).or_insert(0.0) += n;
+        }
+        for ((a, b), n) in codon_pairs {
+            *amino_pairs.entry((amino_of[a], amino_of[b])).or_insert(0.0) += n;
+        }
+
+        // Pairs of amino acids the host never uses are not scored, and unused codon pairs
+        // count as half a use.
+        let mut scores: HashMap<(char, char), f64> = HashMap::new();
+        for (a, n_a) in codons {
+            for (b, n_b) in codons {
+                let (x, y) = (amino_of[a], amino_of[b]);
+                let n_xy = match amino_pairs.get(&(x, y)) {
+                    Some(t) => *t,
+                    None => continue,
+                };
+                let observed = codon_pairs.get(&(*a, *b)).cloned().unwrap_or(0.5);
+                let expected = n_a * n_b / (aminos[x] * aminos[y]) * n_xy;
+                scores.insert((*a, *b), (observed / expected).ln());
+            }
+        }
+        CodonPairScores { scores }
+    }
+
+    // The codon pair bias: the mean score of the codon pairs in an encoded sequence.
+    pub fn score(&self, encoded: &str) -> f64 {
+        let encoded = encoded.chars().collect::<Vec<char>>();
+        let scores = c![*i, for i in encoded.windows(2).filter_map(|j| self.scores.get(&(j[0], j[1])))];
+        if scores.is_empty() {
+            return 0.0;
+        }
+        scores.iter().sum::<f64>() / scores.len() as f64
+    }
+}
+
+/// The region around the start codon whose folding energy is scored.
+pub struct FivePrime {
+    // The 5' UTR upstream of the start codon.
+    pub utr: String,
+    // Start and end of the window, relative to the first nucleotide of the start codon.
+    pub start: i64,
+    pub end: i64,
+    codons: HashMap<char, String>,
+    // Energies of windows already folded, as most candidates share their 5' end with others.
+    folded: Mutex<HashMap<String, f64>>,
+}
+
+// Most windows to remember before forgetting them all.
+const MAX_FOLDED: usize = 100_000;
+
+impl FivePrime {
+    pub fn new(utr: &str, start: i64, end: i64, singular_codon_map: &HashMap<&str, &str>) -> FivePrime {
+        if start >= end {
+            panic!("The start of the 5' window must come before its end");
+        }
+        FivePrime {
+            utr: utr.to_uppercase(),
+            start,
+            end,
+            codons: c! {i.0.chars().next().unwrap() => i.1.to_string(), for i in singular_codon_map},
+            folded: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // The nucleotides of the window for an encoded sequence, clipped to the UTR and the design.
+    pub fn window(&self, encoded: &str) -> String {
+        let utr_start = (self.utr.len() as i64 + self.start.min(0)).max(0) as usize;
+        let upstream = if self.end < 0 {
+            &self.utr[utr_start..(self.utr.len() as i64 + self.end).max(0) as usize]
+        } else {
+            &self.utr[utr_start..]
+        };
+        // Only the codons the window reaches need decoding.
+        let n_codons = (self.end.max(0) as usize).div_ceil(3);
+        let downstream = c![self.codons[&i].as_str(), for i in encoded.chars().take(n_codons)].concat();
+        let downstream_start = (self.start.max(0) as usize).min(downstream.len());
+        let downstream_end = (self.end.max(0) as usize).min(downstream.len());
+        format!("{}{}", upstream, &downstream[downstream_start..downstream_end])
+    }
+
+    // The minimum free energy of the window; higher energies mean less structure.
+    pub fn mfe(&self, encoded: &str) -> f64 {
+        let window = self.window(encoded);
+        if let Some(t) = self.folded.lock().unwrap().get(&window) {
+            return *t;
+        }
+        let energy = folding::mfe(&window);
+        let mut folded = self.folded.lock().unwrap();
+        if folded.len() >= MAX_FOLDED {
+            folded.clear();
+        }
+        folded.insert(window, energy);
+        energy
+    }
+}
+
+/// What codon harmonisation matches between a design and the native CDS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HarmonyMode {
+    // The per-position ARS profile, relative to its mean.
+    Ars,
+    // The relative adaptiveness of the codon at each position.
+    Usage,
+}
+
+impl HarmonyMode {
+    pub fn parse(mode: &str) -> HarmonyMode {
+        match mode {
+            "ars" => HarmonyMode::Ars,
+            "usage" => HarmonyMode::Usage,
+            _ => panic!("Harmony mode given is not supported (ars, usage)"),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HarmonyMode::Ars => "ars",
+            HarmonyMode::Usage => "usage",
+        }
+    }
+}
+
+fn relative(profile: &[f64]) -> Vec<f64> {
+    // Divides a profile by its mean, so profiles from hosts with more or fewer CDSs can be compared.
+    let mean = profile.iter().sum::<f64>() / profile.len() as f64;
+    if mean > 0.0 {
+        c![i / mean, for i in profile]
+    } else {
+        profile.to_vec()
+    }
+}
+
+fn mean_difference(a: &[f64], b: &[f64]) -> f64 {
+    let n = a.len().min(b.len());
+    if n == 0 {
+        return 0.0;
+    }
+    a.iter().zip(b.iter()).map(|(i, j)| (i - j).abs()).sum::<f64>() / n as f64
+}
+
+/// The profile of the native CDS of a protein in its source organism, for codon harmonisation.
+pub struct Harmony {
+    pub mode: HarmonyMode,
+    // Relative per-position ARS of the native CDS against the source organism's CDSs.
+    profile: Vec<f64>,
+    // Relative adaptiveness of each native codon in the source organism, where it is scored.
+    usage: Vec<Option<f64>>,
+}
+
+impl Harmony {
+    pub fn new(mode: HarmonyMode, native_profile: &[f64], native: &str, source: &CodonWeights) -> Harmony {
+        Harmony {
+            mode,
+            profile: relative(native_profile),
+            usage: c![source.weight(i), for i in native.chars()],
+        }
+    }
+
+    // Scores how closely a design matches the native profile, from 0 for a perfect match downwards.
+    pub fn score(&self, encoded: &str, profile: &[f64], host: &CodonWeights) -> f64 {
+        match self.mode {
+            HarmonyMode::Ars => -mean_difference(&relative(profile), &self.profile),
+            HarmonyMode::Usage => {
+                let pairs = encoded
+                    .chars()
+                    .zip(self.usage.iter())
+                    .filter_map(|(i, j)| match (host.weight(i), j) {
+                        (Some(t), Some(u)) => Some((t, *u)),
+                        _ => None,
+                    })
+                    .collect::<Vec<(f64, f64)>>();
+                -mean_difference(&c![i.0, for i in &pairs], &c![i.1, for i in &pairs])
+            }
+        }
+    }
+}
+
+/// A score to optimise designs for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Objective {
+    Ars,
+    Cai,
+    Tai,
+    Cpb,
+    Mfe,
+    Harmony,
+}
+
+impl Objective {
+    pub fn parse(name: &str) -> Objective {
+        match name.to_lowercase().as_str() {
+            "ars" => Objective::Ars,
+            "cai" => Objective::Cai,
+            "tai" => Objective::Tai,
+            "cpb" => Objective::Cpb,
+            "mfe" => Objective::Mfe,
+            "harmony" => Objective::Harmony,
+            _ => panic!("Unknown objective: {} (expected ars, cai, tai, cpb, mfe or harmony)", name),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Objective::Ars => "ars",
+            Objective::Cai => "cai",
+            Objective::Tai => "tai",
+            Objective::Cpb => "cpb",
+            Objective::Mfe => "mfe",
+            Objective::Harmony => "harmony",
+        }
+    }
+}
+
+/// A minimum score a design must reach against every host.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Threshold {
+    pub objective: Objective,
+    pub min: f64,
+}
+
+impl Threshold {
+    // Parses a threshold given as OBJECTIVE=MIN, e.g. cpb=0.05.
+    pub fn parse(text: &str) -> Threshold {
+        let fields = text.splitn(2, '=').collect::<Vec<&str>>();
+        if fields.len() != 2 {
+            panic!("Thresholds must be given as OBJECTIVE=MIN, not {}", text);
+        }
+        Threshold {
+            objective: Objective::parse(fields[0].trim()),
+            min: match fields[1].trim().parse::<f64>() {
+                Ok(t) => t,
+                Err(_) => panic!("Invalid minimum in threshold {}", text),
+            },
+        }
+    }
+}
+
+/// The objectives to optimise designs for, and how to combine them.
+pub struct Objectives {
+    pub kinds: Vec<Objective>,
+    pub weights: Vec<f64>,
+    // Select candidates by Pareto dominance over the objectives, rather than by their weighted sum.
+    pub pareto: bool,
+    // Codon weights for each host, in the order the hosts were given.
+    pub cai: Vec<CodonWeights>,
+    pub tai: Vec<Option<CodonWeights>>,
+    pub cpb: Vec<CodonPairScores>,
+    pub five_prime: Option<FivePrime>,
+    // The native profile of the protein being optimised, if it is being harmonised.
+    pub harmony: Option<Harmony>,
+    // Minimum scores, and whether to penalise or reject candidates that fall short of them.
+    pub thresholds: Vec<Threshold>,
+    pub threshold_mode: ConstraintMode,
+    pub threshold_penalty: f64,
+}
+
+impl Objectives {
+    pub fn new(kinds: Vec<Objective>, weights: Vec<f64>, pareto: bool) -> Objectives {
+        if weights.len() != kinds.len() {
+            panic!("Length of objective weights and number of objectives given must be equal")
+        }
+        Objectives {
+            kinds,
+            weights,
+            pareto,
+            cai: vec![],
+            tai: vec![],
+            cpb: vec![],
+            five_prime: None,
+            harmony: None,
+            thresholds: vec![],
+            threshold_mode: ConstraintMode::Penalty,
+            threshold_penalty: 1.0,
+        }
+    }
+
+    // True if scoring needs the per-position ARS against each host, as well as the ARS.
+    pub fn needs_profiles(&self) -> bool {
+        let harmony = |i: &Objective| *i == Objective::Harmony;
+        self.kinds.iter().any(harmony) || self.thresholds.iter().any(|i| harmony(&i.objective))
+    }
+
+    // Scores an encoded sequence against each host for an objective, given its ARS, and if needed
+    // its per-position ARS, against each host.
+    pub fn host_scores(&self, objective: Objective, encoded: &str, ars: &[f64], profiles: &[Vec<f64>]) -> Vec<f64> {
+        match objective {
+            Objective::Ars => ars.to_vec(),
+            Objective::Cai => c![i.score(encoded), for i in &self.cai],
+            Objective::Tai => c![match i {
+                Some(t) => t.score(encoded),
+                None => panic!("The tai objective requires a tRNA table for every host"),
+            }, for i in &self.tai],
+            Objective::Cpb => c![i.score(encoded), for i in &self.cpb],
+            // The folding energy does not depend on the host.
+            Objective::Mfe => match &self.five_prime {
+                Some(t) => vec![t.mfe(encoded); ars.len()],
+                None => panic!("The mfe objective requires a 5' window"),
+            },
+            Objective::Harmony => match &self.harmony {
+                Some(t) => profiles
+                    .iter()
+                    .zip(self.cai.iter())
+                    .map(|(i, j)| t.score(encoded, i, j))
+                    .collect(),
+                None => panic!("The harmony objective requires a native CDS and a source organism"),
+            },
+        }
+    }
+
+    // Sums how far the scores against each host fall below the thresholds.
+    pub fn shortfall(&self, encoded: &str, ars: &[f64], profiles: &[Vec<f64>]) -> f64 {
+        self.thresholds
+            .iter()
+            .flat_map(|i| {
+                self.host_scores(i.objective, encoded, ars, profiles)
+                    .into_iter()
+                    .map(move |j| (i.min - j).max(0.0))
+            })
+            .sum()
+    }
+
+    // Penalises or rejects candidates that fall short of the thresholds.
+    pub fn adjust_fitness(&self, encoded: &str, ars: &[f64], profiles: &[Vec<f64>], fitness: f64) -> f64 {
+        if self.thresholds.is_empty() {
+            return fitness;
+        }
+        let shortfall = self.shortfall(encoded, ars, profiles);
+        match self.threshold_mode {
+            ConstraintMode::Reject if shortfall > 0.0 => f64::NEG_INFINITY,
+            ConstraintMode::Penalty => fitness - self.threshold_penalty * shortfall,
+            _ => fitness,
+        }
+    }
+
+    // Combines the scores for each objective into one, weighting each objective.
+    pub fn combine(&self, scores: &[f64]) -> f64 {
+        let total = scores.iter().zip(self.weights.iter()).map(|(i, j)| i * j).sum::<f64>();
+        total / self.weights.iter().sum::<f64>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translation_tables;
+    use std::io::Write;
+
+    // Counts the codons of encoded CDSs joined by separators.
+    fn usage(encoded_cds: &str) -> CodonUsage {
+        let mut usage = CodonUsage::default();
+        encoded_cds.split('|').for_each(|i| usage.add(i));
+        usage
+    }
+
+    #[test]
+    fn test_cai() {
+        let (codon_singular_map, _, _, _, amino_singular_map, _) =
+            translation_tables::generate_maps(translation_tables::tt11());
+        let encode = |i: &[&str]| c![*codon_singular_map.get(j).unwrap(), for j in i].concat();
+        // The host uses GAA three times and GAG once for glutamate.
+        let host = encode(&["GAA", "GAA", "GAA", "GAG", "ATG"]);
+        let weights = CodonWeights::cai(&usage(&host), &amino_singular_map);
+        assert_eq!(weights.score(&encode(&["GAA", "GAA"])), 1.0);
+        assert!((weights.score(&encode(&["GAG"])) - 1.0 / 3.0).abs() < 1e-12);
+        // Methionine is not scored.
+        assert!((weights.score(&encode(&["GAA", "GAG", "ATG"])) - (1.0_f64 / 3.0).sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_tai() {
+        let (codon_singular_map, _, codon_amino_map, _, _, _) =
+            translation_tables::generate_maps(translation_tables::tt11());
+        let encode = |i: &[&str]| c![*codon_singular_map.get(j).unwrap(), for j in i].concat();
+        let path = std::env::temp_dir().join("chimera_evolve_test_trna.txt");
+        let mut file = fs::File::create(&path).unwrap();
+        writeln!(file, "# anticodon copies\nTTC 4\nCTC 1").unwrap();
+        let weights = CodonWeights::tai(path.to_str().unwrap(), &codon_singular_map, &codon_amino_map);
+        // GAA is decoded by TTC, and GAG by CTC and, with a wobble penalty, by TTC.
+        assert_eq!(weights.score(&encode(&["GAA"])), 1.0);
+        assert!((weights.score(&encode(&["GAG"])) - (1.0 + 4.0 * (1.0 - S_UG)) / 4.0).abs() < 1e-12);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_cpb() {
+        let (codon_singular_map, _, _, _, amino_singular_map, _) =
+            translation_tables::generate_maps(translation_tables::tt11());
+        let encode = |i: &[&str]| c![*codon_singular_map.get(j).unwrap(), for j in i].concat();
+        // The host always follows GAA with AAA, and GAG with AAG.
+        let host = [
+            encode(&["GAA", "AAA", "GAG", "AAG"]),
+            encode(&["GAA", "AAA", "GAG", "AAG"]),
+        ]
+        .join("|");
+        let scores = CodonPairScores::new(&usage(&host), &amino_singular_map);
+        // GAA-AAA is seen twice, and expected once; GAA-AAG is never seen.
+        assert!((scores.score(&encode(&["GAA", "AAA"])) - 2.0_f64.ln()).abs() < 1e-12);
+        assert!((scores.score(&encode(&["GAA", "AAG"])) - 0.5_f64.ln()).abs() < 1e-12);
+        assert!(scores.score(&encode(&["GAA", "AAA"])) > scores.score(&encode(&["GAA", "AAG"])));
+        assert_eq!(Threshold::parse("cpb=0.05"), Threshold { objective: Objective::Cpb, min: 0.05 });
+    }
+
+    #[test]
+    fn test_five_prime_window() {
+        let (codon_singular_map, singular_codon_map, _, _, _, _) =
+            translation_tables::generate_maps(translation_tables::tt11());
+        let encode = |i: &[&str]| c![*codon_singular_map.get(j).unwrap(), for j in i].concat();
+        let encoded = encode(&["ATG", "GCT", "AAA", "TTT"]);
+        let window = FivePrime::new("GGAGGTTTCC", -4, 5, &singular_codon_map);
+        assert_eq!(window.window(&encoded), "TTCCATGGC");
+        // Windows are clipped to the UTR and the design.
+        let window = FivePrime::new("CC", -20, 50, &singular_codon_map);
+        assert_eq!(window.window(&encoded), "CCATGGCTAAATTT");
+        let window = FivePrime::new("", 3, 6, &singular_codon_map);
+        assert_eq!(window.window(&encoded), "GCT");
+    }
+
+    #[test]
+    fn test_harmony() {
+        let (codon_singular_map, _, _, _, amino_singular_map, _) =
+            translation_tables::generate_maps(translation_tables::tt11());
+        let encode = |i: &[&str]| c![*codon_singular_map.get(j).unwrap(), for j in i].concat();
+        let host = CodonWeights::cai(&usage(&encode(&["GAA", "GAA", "GAA", "GAG"])), &amino_singular_map);
+        let native = encode(&["GAA", "GAG"]);
+        // The source organism uses both glutamate codons equally.
+        let source = CodonWeights::cai(&usage(&encode(&["GAA", "GAG"])), &amino_singular_map);
+
+        // Profiles with the same shape match perfectly, whatever their scale.
+        let harmony = Harmony::new(HarmonyMode::Ars, &[1.0, 3.0], &native, &source);
+        assert_eq!(harmony.score(&native, &[2.0, 6.0], &host), 0.0);
+        assert_eq!(harmony.score(&native, &[2.0, 2.0], &host), -0.5);
+
+        // GAG is as common as GAA in the source, so GAA, the common codon in the host, matches it best.
+        let harmony = Harmony::new(HarmonyMode::Usage, &[], &native, &source);
+        assert_eq!(harmony.score(&encode(&["GAA", "GAA"]), &[], &host), 0.0);
+        assert!(harmony.score(&native, &[], &host) < 0.0);
+    }
+}
@@ -0,0 +1,365 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::f64;
+use crate::cache::{hit_rate, Cache};
+use crate::ealgorithm::{self, Candidate, Outcome, Parameters, Scored, StopReason};
+use crate::matching::MatchIndex;
+use crate::trace::Trace;
+
+use crate::rayon::iter::IntoParallelRefMutIterator;
+use crate::rayon::iter::ParallelIterator;
+
+/// What is optimised: a protein, the hosts its candidates are scored against, the codons that can
+/// encode it, and the parameters of the run.
+pub struct Problem<'a> {
+    pub protein: &'a str,
+    pub indexes: &'a [MatchIndex],
+    pub amino_singular: &'a HashMap<&'a str, Vec<&'a str>>,
+    pub alternatives: &'a HashMap<&'a str, Vec<&'a str>>,
+    pub params: &'a Parameters<'a>,
+}
+
+/// A search for designs of a protein, advanced one generation at a time. Every optimiser scores
+/// its candidates with `Candidate::score`, and shares the fitness cache, trace and stopping rules.
+pub trait Optimiser {
+    // Creates and scores the first generation.
+    fn start(&mut self, problem: &Problem, cache: &Cache<Vec<u8>, Scored>);
+    // Advances the search by one generation, scoring every new candidate.
+    fn step(&mut self, gen: usize, problem: &Problem, cache: &Cache<Vec<u8>, Scored>);
+    // The scored candidates held, which are traced and from which the designs are chosen.
+    fn population(&self) -> Vec<&Candidate>;
+    // The number of times the search started again.
+    fn restarts(&self) -> usize {
+        0
+    }
+}
+
+/// How the temperature falls during simulated annealing: by a constant factor each generation, or
+/// in equal steps to zero at the last generation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cooling {
+    Geometric(f64),
+    Linear,
+}
+
+impl Cooling {
+    pub fn parse(name: &str, rate: f64) -> Cooling {
+        match name {
+            "geometric" => Cooling::Geometric(rate),
+            "linear" => Cooling::Linear,
+            _ => panic!("Cooling must be geometric or linear"),
+        }
+    }
+
+    fn temperature(&self, initial: f64, gen: usize, n_gen: usize) -> f64 {
+        match self {
+            Cooling::Geometric(t) => initial * t.powi(gen as i32),
+            Cooling::Linear => initial * (1.0 - gen as f64 / n_gen as f64),
+        }
+    }
+}
+
+// Sorts candidates from the fittest, and keeps the n fittest distinct ones.
+fn keep_fittest(population: &mut Vec<Candidate>, n: usize) {
+    population.sort_by(|a, b| b.fitness.unwrap().partial_cmp(&a.fitness.unwrap()).unwrap());
+    ealgorithm::deduplicate(population);
+    population.truncate(n);
+}
+
+/// Simulated annealing of one candidate, which moves to a mutated copy of itself if that is at
+/// least as fit, or otherwise with a probability that falls as the temperature cools.
+pub struct Annealing {
+    temperature: f64,
+    cooling: Cooling,
+    rng: StdRng,
+    current: Candidate,
+    // The fittest distinct candidates moved to so far.
+    archive: Vec<Candidate>,
+}
+
+impl Annealing {
+    pub fn new(temperature: f64, cooling: Cooling, random_seed: u64) -> Annealing {
+        Annealing {
+            temperature,
+            cooling,
+            rng: StdRng::seed_from_u64(random_seed),
+            current: Candidate::default(),
+            archive: vec![],
+        }
+    }
+}
+
+impl Optimiser for Annealing {
+    fn start(&mut self, problem: &Problem, cache: &Cache<Vec<u8>, Scored>) {
+        // Anneal from the fittest candidate of a first generation made as for the EA.
+        let params = problem.params;
+        let mut population = ealgorithm::initial_population(0, problem, &mut self.rng);
+        population
+            .par_iter_mut()
+            .for_each(|i| i.score(problem.indexes, params, cache));
+        keep_fittest(&mut population, params.n_gen_start.max(params.top));
+        self.current = population[0].clone();
+        self.archive = population;
+    }
+
+    fn step(&mut self, gen: usize, problem: &Problem, cache: &Cache<Vec<u8>, Scored>) {
+        // Make as many moves as the EA makes new candidates in a generation.
+        let params = problem.params;
+        let temperature = self.cooling.temperature(self.temperature, gen, params.n_gen);
+        for _ in 0..params.n_cross * 2 + params.n_mut {
+            let mut candidate = self.current.mutate(5, problem.alternatives, params.locked, &mut self.rng);
+            candidate.score(problem.indexes, params, cache);
+            let (new, old) = (candidate.fitness.unwrap(), self.current.fitness.unwrap());
+            // Any move away from a rejected candidate is taken, and none to one.
+            if !old.is_finite() || new >= old || self.rng.gen::<f64>() < ((new - old) / temperature).exp() {
+                self.archive.push(candidate.clone());
+                self.current = candidate;
+            }
+        }
+        keep_fittest(&mut self.archive, params.n_gen_start.max(params.top));
+    }
+
+    fn population(&self) -> Vec<&Candidate> {
+        self.archive.iter().collect()
+    }
+}
+
+/// An estimation of distribution algorithm, which samples candidates from a probability for each
+/// codon at each position, and moves those probabilities towards the codon frequencies of the
+/// fittest candidates: by the learning rate as in PBIL, or all the way as in UMDA (a rate of 1).
+pub struct Distribution {
+    learning_rate: f64,
+    rng: StdRng,
+    // The codon codes that can be used at each position, and their probabilities.
+    model: Vec<Vec<(u8, f64)>>,
+    // The fittest distinct candidates found so far.
+    population: Vec<Candidate>,
+}
+
+impl Distribution {
+    pub fn new(learning_rate: f64, random_seed: u64) -> Distribution {
+        Distribution {
+            learning_rate,
+            rng: StdRng::seed_from_u64(random_seed),
+            model: vec![],
+            population: vec![],
+        }
+    }
+
+    fn sample(&mut self, params: &Parameters) -> Candidate {
+        let rng = &mut self.rng;
+        let sequence = c![{
+            let mut choice = rng.gen::<f64>();
+            let mut code = i[i.len() - 1].0;
+            for (j, p) in i {
+                if choice < *p {
+                    code = *j;
+                    break;
+                }
+                choice -= p;
+            }
+            code as char
+        }, for i in &self.model].into_iter().collect::<String>();
+        let sequence = match params.template {
+            Some(t) => ealgorithm::apply_template(&sequence, t, params.locked),
+            None => sequence,
+        };
+        Candidate::new(sequence)
+    }
+
+    fn learn(&mut self) {
+        // Moves the probabilities towards the codon frequencies of the population.
+        let n = self.population.len() as f64;
+        for (idx, codes) in self.model.iter_mut().enumerate() {
+            for (code, p) in codes.iter_mut() {
+                let count = self.population.iter().filter(|i| i.sequence[idx] == *code).count();
+                *p += self.learning_rate * (count as f64 / n - *p);
+            }
+        }
+    }
+}
+
+impl Optimiser for Distribution {
+    fn start(&mut self, problem: &Problem, cache: &Cache<Vec<u8>, Scored>) {
+        // Every codon starts equally likely, and the model then learns from a first generation
+        // made as for the EA, which includes any seeds.
+        let params = problem.params;
+        self.model = c![{
+            let codes = &problem.amino_singular[i.to_string().as_str()];
+            c![(j.as_bytes()[0], 1.0 / codes.len() as f64), for j in codes]
+        }, for i in problem.protein.chars()];
+        let mut population = ealgorithm::initial_population(0, problem, &mut self.rng);
+        population
+            .par_iter_mut()
+            .for_each(|i| i.score(problem.indexes, params, cache));
+        keep_fittest(&mut population, params.n_gen_start.max(params.top));
+        self.population = population;
+        self.learn();
+    }
+
+    fn step(&mut self, _gen: usize, problem: &Problem, cache: &Cache<Vec<u8>, Scored>) {
+        // Sample as many candidates as the EA makes in a generation, keep the fittest of those and
+        // the population, and learn from them.
+        let params = problem.params;
+        let mut samples = c![self.sample(params), for _i in 0..params.n_cross * 2 + params.n_mut];
+        samples
+            .par_iter_mut()
+            .for_each(|i| i.score(problem.indexes, params, cache));
+        self.population.extend(samples);
+        keep_fittest(&mut self.population, params.n_gen_start.max(params.top));
+        self.learn();
+    }
+
+    fn population(&self) -> Vec<&Candidate> {
+        self.population.iter().collect()
+    }
+}
+
+// Makes the optimiser with the given name.
+pub fn parse(
+    name: &str,
+    temperature: f64,
+    cooling: Cooling,
+    learning_rate: f64,
+    random_seed: u64,
+) -> Box<dyn Optimiser> {
+    match name {
+        "ea" => Box::new(ealgorithm::Evolution::default()),
+        "annealing" => Box::new(Annealing::new(temperature, cooling, random_seed)),
+        "eda" => Box::new(Distribution::new(learning_rate, random_seed)),
+        _ => panic!("Optimiser must be ea, annealing or eda"),
+    }
+}
+
+pub fn run(optimiser: &mut dyn Optimiser, problem: &Problem, mut trace: Option<&mut Trace>) -> Outcome {
+    let params = problem.params;
+    let n_gen = params.n_gen;
+    // Scores are cached across generations, and the hit rate traced for each generation.
+    let cache = Cache::new(params.cache_size);
+    let mut counts = (0, 0);
+    optimiser.start(problem, &cache);
+    let best = |optimiser: &dyn Optimiser| {
+        optimiser
+            .population()
+            .iter()
+            .map(|i| i.fitness.unwrap())
+            .fold(f64::NAN, f64::max)
+    };
+
+    let mut highest_fitness = best(optimiser);
+    info!(
+        "Generation 0 : score of best candidate = {}",
+        highest_fitness
+    );
+    if let Some(t) = trace.as_mut() {
+        let (hits, lookups) = cache.counts();
+        t.record(&ealgorithm::summarise(0, &optimiser.population(), hit_rate(hits - counts.0, lookups - counts.1)));
+        counts = (hits, lookups);
+    }
+
+    // Track the last generation in which the best score improved.
+    let mut last_improvement = 0;
+    let mut stop_reason = StopReason::GenerationLimit;
+    let mut generations = n_gen;
+
+    // For each of the remaining generations.
+    for gen in 1..n_gen {
+        optimiser.step(gen, problem, &cache);
+
+        let best = best(optimiser);
+        if best > highest_fitness {
+            highest_fitness = best;
+            last_improvement = gen;
+        }
+        if let Some(t) = trace.as_mut() {
+            let (hits, lookups) = cache.counts();
+            t.record(&ealgorithm::summarise(gen, &optimiser.population(), hit_rate(hits - counts.0, lookups - counts.1)));
+            counts = (hits, lookups);
+        }
+
+        // Report
+        if gen % 100 == 0 {
+            info!(
+                "Generation {} : score of best candidate = {}",
+                gen, highest_fitness
+            );
+        }
+
+        // Stop if the best score has stalled.
+        if let Some(t) = params.stall {
+            if gen - last_improvement >= t {
+                info!("Generation {} : no improvement for {} generations", gen, t);
+                stop_reason = StopReason::Stalled;
+                generations = gen + 1;
+                break;
+            }
+        }
+    }
+
+    // Report
+    info!(
+        "Algorithm complete -- highest fitness {}",
+        highest_fitness
+    );
+
+    // Get the highest scoring distinct designs.
+    let population = c![(*i).clone(), for i in optimiser.population()];
+    let designs = ealgorithm::select_designs(
+        &population,
+        params.top,
+        params.min_distance,
+        params.objectives.pareto,
+    );
+    let mut valid = c![&i.sequence, for i in &population, if i.fitness.unwrap().is_finite()];
+    valid.sort();
+    valid.dedup();
+    if valid.len() < params.top {
+        warn!(
+            "Only {} distinct designs meet the thresholds and constraints, of {} requested",
+            valid.len(),
+            params.top
+        );
+    } else if designs.len() < params.top {
+        warn!(
+            "Only {} designs at least {} codons apart were found",
+            designs.len(),
+            params.min_distance
+        );
+    }
+
+    let (hits, lookups) = cache.counts();
+    Outcome {
+        designs,
+        generations,
+        stop_reason,
+        cache_hit_rate: hit_rate(hits, lookups),
+        restarts: optimiser.restarts(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cooling() {
+        let geometric = Cooling::parse("geometric", 0.5);
+        assert_eq!(c![geometric.temperature(1.0, i, 4), for i in 0..4], vec![1.0, 0.5, 0.25, 0.125]);
+        let linear = Cooling::parse("linear", 0.5);
+        assert_eq!(c![linear.temperature(1.0, i, 4), for i in 0..4], vec![1.0, 0.75, 0.5, 0.25]);
+    }
+    #[test]
+    fn test_learn() {
+        let mut eda = Distribution::new(0.5, 1);
+        eda.model = vec![vec![(b'a', 0.5), (b'b', 0.5)], vec![(b'c', 1.0)]];
+        eda.population = c![Candidate::new(i.to_string()), for i in &["ac", "ac", "ac", "bc"]];
+        // Each probability moves halfway to the codon's frequency in the population.
+        eda.learn();
+        assert_eq!(eda.model, vec![vec![(b'a', 0.625), (b'b', 0.375)], vec![(b'c', 1.0)]]);
+        // With a rate of 1, as in UMDA, the probabilities are the frequencies.
+        eda.learning_rate = 1.0;
+        eda.learn();
+        assert_eq!(eda.model, vec![vec![(b'a', 0.75), (b'b', 0.25)], vec![(b'c', 1.0)]]);
+    }
+}
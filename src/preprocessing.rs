@@ -1,5 +1,5 @@
 use rayon::prelude::*;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use suffix::SuffixTable;
 
 #[allow(dead_code)]
@@ -13,116 +13,222 @@ pub fn translate_codon_str_to_alphabet(codon_string: &str, map: &HashMap<&str, &
         .collect::<String>()
 }
 
-fn condensed_encoding_substring(
-    encoded_substring: &SuffixTable,
+// Given a hash map and encoded string, converts the encoded string back to codons.
+pub fn encoding_to_nuc(encoded_str: &str, map: &HashMap<&str, &str> ) -> String {
+    let mut nucleotide_string = String::new();
+    encoded_str.chars().for_each(|i| {
+        let i_str = i.to_string();
+        nucleotide_string.push_str( map.get::<str>(&i_str).unwrap() );
+    });
+    nucleotide_string
+}
+
+fn extend_interval(table: &SuffixTable, lo: usize, hi: usize, depth: usize, code: u8) -> (usize, usize) {
+    // Narrows a range of the suffix array, whose suffixes share a prefix of the given length, to
+    // those followed by the given codon code.
+    let (suffixes, text) = (&table.table()[lo..hi], table.text().as_bytes());
+    let key = |i: &u32| text.get(*i as usize + depth).cloned();
+    let start = suffixes.partition_point(|i| key(i) < Some(code));
+    let end = suffixes.partition_point(|i| key(i) <= Some(code));
+    (lo + start, lo + end)
+}
+
+fn condensed_encoding_substring<'a>(
+    table: &'a SuffixTable,
     aa_seq: &str,
+    idx: usize,
     map: &HashMap<&str, Vec<&str>>,
-) -> HashSet<String> {
-    // This function gets the relevant substrings encoding the start of a given substring.
-    // Create an iterator for the substring.
-    let mut aa_iter = aa_seq.chars();
-
-    let start = aa_iter.next();
-    // Check to ensure this is not None.
-    match start {
-        Some(_t) => {}
-        None => panic!("The condensed encoding substring should not be empty."),
-    }
-
-    // Get an initial vector
-    let initial_vec: &Vec<&str> = map.get::<str>(&start.unwrap().to_string()).unwrap();
-    // Make a new vector, that contains the strings to expand.
-    let mut current_vec: Vec<String> = c![i.to_string(), for i in initial_vec];
-    // Make a set to hold all possible encoding substrings.
-    let mut all_substrings: HashSet<String> = HashSet::new();
-
-    loop {
-        // Update current vector.
-        current_vec = current_vec
-            .iter()
-            // Filter out members that are not in the encoded substring.
-            .filter(|i| encoded_substring.contains(*i))
-            // Add remaining substrings to all_substrings.
-            .map(|i| {
-                all_substrings.insert(i.to_string());
-                i.to_string()
-            })
-            // And collect them in a vector
-            .collect::<Vec<String>>();
-
-
-        // If the current vector is broken (nothing to extend), then break.
-        if current_vec.is_empty() {
-            break;
+) -> Vec<&'a str> {
+    // Finds the host substrings encoding the protein from idx that cannot be extended by a codon
+    // of the next amino acid. Each is found as a range of the suffix array, so no combinations of
+    // codons are built that the host does not contain.
+    let codes = |i: usize| c![j.as_bytes()[0], for j in map.get::<str>(&aa_seq[i..=i]).unwrap()];
+    let mut found = vec![];
+    let mut stack = vec![(0, table.len(), 0)];
+    while let Some((lo, hi, depth)) = stack.pop() {
+        let mut extended = false;
+        if idx + depth < aa_seq.len() {
+            for code in codes(idx + depth) {
+                let (start, end) = extend_interval(table, lo, hi, depth, code);
+                if start < end {
+                    stack.push((start, end, depth + 1));
+                    extended = true;
+                }
+            }
         }
-        // Get the next char
-        let next_char = aa_iter.next();
-        // If there's no next char, break the loop.
-        match next_char {
-            Some(_t) => {}
-            None => break,
+        if !extended && depth > 0 {
+            let position = table.table()[lo] as usize;
+            found.push(&table.text()[position..position + depth]);
         }
+    }
 
-        // Update current_vec to create new possible strings up to the next amino acid.
-        current_vec = current_vec
-            .iter()
-            .map(|i| {
-                let mut tmp_vec: Vec<String> = vec![];
-                let options = map.get::<str>(&next_char.unwrap().to_string()).unwrap();
-                for item in options.iter() {
-                    let new_item = format!("{}{}", i, item);
-                    tmp_vec.push(new_item);
-                }
-                tmp_vec
-            })
-            .flatten()
-            .collect::<Vec<String>>();
+    // Leave out substrings found in full from the previous amino acid.
+    if idx == 0 {
+        return found;
     }
+    let previous = codes(idx - 1);
+    c![i, for i in found, if !previous.iter().any(|j| table.contains(&format!("{}{}", *j as char, i)))]
+}
 
-    all_substrings
-        .iter()
-        .filter(|i| {
-            for j in &all_substrings {
-                if j.contains(*i) && j != *i {
-                    return false;
+// Finds, for each position, the longest host substring starting there that is made of the codon
+// codes allowed at each position, which is the longest match any encoding can have there.
+pub fn longest_matches(table: &SuffixTable, codes: &[Vec<u8>]) -> Vec<usize> {
+    (0..codes.len())
+        .into_par_iter()
+        .map(|idx| {
+            let mut longest = 0;
+            let mut stack = vec![(0, table.len(), 0)];
+            while let Some((lo, hi, depth)) = stack.pop() {
+                longest = longest.max(depth);
+                if idx + depth < codes.len() {
+                    for code in &codes[idx + depth] {
+                        let (start, end) = extend_interval(table, lo, hi, depth, *code);
+                        if start < end {
+                            stack.push((start, end, depth + 1));
+                        }
+                    }
                 }
             }
-            true
+            longest
         })
-        .cloned()
-        .collect::<HashSet<String>>()
+        .collect()
 }
 
-pub fn condense_encoding<'a, 'b>(
-    encoded_cds: &str,
-    aa_seq: &str,
-    map: &HashMap<&str, Vec<&str>>,
-) -> SuffixTable<'a, 'b> {
-    // This function creates a condensed encoding based on the encoded CDS and AA seq.
-    // Create a suffix table
+// Builds a suffix table of a host's encoded CDSs, from which the condensed encoding for each
+// protein is found.
+pub fn host_table(encoded_cds: String) -> SuffixTable<'static, 'static> {
     info!("Generating suffix table for full CDS");
-    let st = SuffixTable::new(encoded_cds);
+    SuffixTable::new(encoded_cds)
+}
+
+fn condensed_substrings<'a>(table: &'a SuffixTable, aa_seq: &str, map: &HashMap<&str, Vec<&str>>) -> Vec<&'a str> {
+    // Every host substring that encodes part of the protein is contained in one of the substrings
+    // kept, so ARS against the condensed encoding is the same as against the full CDS.
+    let mut substrings = (0..aa_seq.len())
+        .into_par_iter()
+        .flat_map(|idx| condensed_encoding_substring(table, aa_seq, idx, map))
+        .collect::<Vec<&str>>();
+    substrings.sort_unstable();
+    substrings.dedup();
+    substrings
+}
+
+fn join_substrings<T: AsRef<str>>(substrings: &[T]) -> String {
+    let mut final_string = c![i.as_ref(), for i in substrings].join("|");
+    final_string.push('|');
+    final_string
+}
 
-    // Get all the relevant substrings
+// Finds the condensed encoding of a protein from the host suffix table: the host substrings that
+// encode part of it, joined by separators.
+pub fn condensed_text(table: &SuffixTable, aa_seq: &str, map: &HashMap<&str, Vec<&str>>) -> String {
     info!("Obtaining relevant substrings from host CDS, given protein");
-    let mut substrings = aa_seq
-        .chars()
-        .enumerate()
-        .par_bridge()
-        .map(|(idx, _)| condensed_encoding_substring(&st, &aa_seq[idx..], &map))
-        .flatten()
-        .collect::<Vec<String>>();
-    info!("Compressing substrings into single string");
-    substrings.sort_by(|a, b| a.len().cmp(&b.len()));
-
-    let mut final_string = String::new();
-    for string in substrings.iter().rev() {
-        if !final_string.contains(string) {
-            final_string.push_str(string);
-            final_string.push('|');
+    join_substrings(&condensed_substrings(table, aa_seq, map))
+}
+
+// Approximate memory used to build a suffix table, per codon of text.
+const BYTES_PER_CODON: usize = 10;
+
+// The most codons to index at once within a memory limit in megabytes.
+pub fn chunk_size(memory_limit: usize) -> usize {
+    (memory_limit * 1_000_000 / BYTES_PER_CODON).max(1)
+}
+
+/// Finds the condensed encodings of proteins from host CDSs given one at a time, holding no more
+/// than a chunk of them and its suffix table in memory. Matches never run across the separators
+/// between CDSs, so the substrings found in each chunk together give the same ARS as those found
+/// in all of the CDSs at once.
+pub struct Condenser<'a> {
+    proteins: Vec<&'a str>,
+    map: &'a HashMap<&'a str, Vec<&'a str>>,
+    // Most codons in a chunk, the CDSs of the current chunk, and the substrings found so far for
+    // each protein.
+    chunk_size: usize,
+    chunk: String,
+    substrings: Vec<Vec<String>>,
+}
+
+impl<'a> Condenser<'a> {
+    pub fn new(proteins: Vec<&'a str>, map: &'a HashMap<&'a str, Vec<&'a str>>, chunk_size: usize) -> Condenser<'a> {
+        Condenser {
+            substrings: vec![vec![]; proteins.len()],
+            proteins,
+            map,
+            chunk_size,
+            chunk: String::new(),
+        }
+    }
+
+    // Adds an encoded CDS, condensing the chunk first if it would not fit.
+    pub fn push(&mut self, encoded_cds: &str) {
+        if !self.chunk.is_empty() && self.chunk.len() + encoded_cds.len() + 1 > self.chunk_size {
+            self.condense();
+        }
+        self.chunk.push_str(encoded_cds);
+        self.chunk.push('|');
+    }
+
+    fn condense(&mut self) {
+        info!("Condensing a chunk of {} codons", self.chunk.len());
+        let table = SuffixTable::new(self.chunk.as_str());
+        for (protein, substrings) in self.proteins.iter().zip(self.substrings.iter_mut()) {
+            substrings.extend(c![i.to_string(), for i in condensed_substrings(&table, protein, self.map)]);
+            substrings.sort_unstable();
+            substrings.dedup();
+        }
+        self.chunk.clear();
+    }
+
+    // Condenses the last chunk, and returns the condensed encoding of each protein.
+    pub fn finish(mut self) -> Vec<String> {
+        if !self.chunk.is_empty() {
+            self.condense();
+        }
+        c![join_substrings(i), for i in &self.substrings]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matching::{MatchIndex, MatchingStatistics};
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_condense_encoding() {
+        // Two amino acids, each with two codons.
+        let map = c! {i.0 => i.1.to_vec(), for i in [("K", ["a", "b"]), ("L", ["c", "d"])]};
+        let host = "abcdacbd|cadbbc|ddaacb|bcadac|";
+        let protein = "KLKKLLKLKK";
+        let (table, full) = (host_table(host.to_string()), SuffixTable::new(host));
+        let index = MatchIndex::new(&condensed_text(&table, protein, &map));
+
+        // Every encoding of the protein matches the condensed encoding as it does the full CDSs.
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..200 {
+            let encoded = c![*map[&protein[i..=i]].choose(&mut rng).unwrap(), for i in 0..protein.len()].concat();
+            assert_eq!(index.match_lengths(&encoded), full.match_lengths(&encoded));
+        }
+    }
+    #[test]
+    fn test_condense_chunks() {
+        let map = c! {i.0 => i.1.to_vec(), for i in [("K", ["a", "b"]), ("L", ["c", "d"])]};
+        let records = ["abcdacbd", "cadbbc", "ddaacb", "bcadac"];
+        let proteins = ["KLKKLLKLKK", "LLKK"];
+        let full = SuffixTable::new("abcdacbd|cadbbc|ddaacb|bcadac|");
+
+        // Chunks of one or two records match every encoding as the full CDSs do.
+        let mut condenser = Condenser::new(proteins.to_vec(), &map, 16);
+        records.iter().for_each(|i| condenser.push(i));
+        let mut rng = StdRng::seed_from_u64(2);
+        for (protein, condensed) in proteins.iter().zip(condenser.finish()) {
+            let index = MatchIndex::new(&condensed);
+            for _ in 0..100 {
+                let encoded = c![*map[&protein[i..=i]].choose(&mut rng).unwrap(), for i in 0..protein.len()].concat();
+                assert_eq!(index.match_lengths(&encoded), full.match_lengths(&encoded));
+            }
         }
     }
-    info!("Final string generated");
-    info!("Creating suffix table");
-    SuffixTable::new(final_string)
 }
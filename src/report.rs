@@ -0,0 +1,104 @@
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+
+// Computes the SHA-256 checksum of a file, as a hex string.
+pub fn checksum(path: &str) -> String {
+    let mut file = File::open(path).unwrap();
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 65536];
+    loop {
+        let n = file.read(&mut buffer).unwrap();
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+// Describes an input file by its path and checksum.
+pub fn input(path: &str) -> Value {
+    json!({
+        "path": path,
+        "sha256": checksum(path),
+    })
+}
+
+// Writes a JSON report to the given file.
+pub fn write_report(outfile: &str, report: &Value) {
+    let file = File::create(outfile).unwrap();
+    let mut buf = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut buf, report).unwrap();
+    buf.write_all(b"\n").unwrap();
+    buf.flush().unwrap();
+}
+
+/// One row of the summary table, describing a single design.
+pub struct SummaryRow<'a> {
+    pub protein: &'a str,
+    pub length: usize,
+    pub design: usize,
+    pub score: f64,
+    pub host_scores: &'a [f64],
+    pub cai: &'a [f64],
+    pub tai: &'a [Option<f64>],
+    pub cpb: &'a [f64],
+    pub mfe: f64,
+    pub sites: usize,
+    pub motifs: usize,
+    pub gc: f64,
+    pub gc_violations: usize,
+    pub repeats: usize,
+    pub generations: usize,
+    pub stop_reason: &'a str,
+}
+
+// Writes a tab-separated summary table with one row per design.
+pub fn write_summary(outfile: &str, host_names: &[String], rows: &[SummaryRow]) {
+    let file = File::create(outfile).unwrap();
+    let mut buf = BufWriter::new(file);
+    let host_columns = [
+        c![format!("\tars_{}", i), for i in host_names].concat(),
+        c![format!("\tcai_{}", i), for i in host_names].concat(),
+        c![format!("\ttai_{}", i), for i in host_names].concat(),
+        c![format!("\tcpb_{}", i), for i in host_names].concat(),
+    ]
+    .concat();
+    writeln!(
+        buf,
+        "protein\tlength\tdesign\tscore{}\tsites\tmotifs\tgc\tgc_violations\trepeats\tmfe\tgenerations\tstop_reason",
+        host_columns
+    )
+    .unwrap();
+    for row in rows {
+        // Hosts without a tRNA table have an empty tAI column.
+        let host_columns = [
+            c![format!("\t{}", i), for i in row.host_scores].concat(),
+            c![format!("\t{}", i), for i in row.cai].concat(),
+            c![format!("\t{}", i.map_or(String::new(), |t| t.to_string())), for i in row.tai].concat(),
+            c![format!("\t{}", i), for i in row.cpb].concat(),
+        ]
+        .concat();
+        writeln!(
+            buf,
+            "{}\t{}\t{}\t{}{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            row.protein,
+            row.length,
+            row.design,
+            row.score,
+            host_columns,
+            row.sites,
+            row.motifs,
+            row.gc,
+            row.gc_violations,
+            row.repeats,
+            row.mfe,
+            row.generations,
+            row.stop_reason
+        )
+        .unwrap();
+    }
+    buf.flush().unwrap();
+}
@@ -0,0 +1,100 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Summary statistics for one generation of the evolutionary algorithm.
+pub struct GenerationStats {
+    pub generation: usize,
+    pub best: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub worst: f64,
+    // Best ARS against each host, in the order the hosts were given.
+    pub host_best: Vec<f64>,
+    // Mean pairwise proportion of codons that differ between members of the population.
+    pub diversity: f64,
+    pub unique: usize,
+    // Proportion of the candidates scored this generation whose scores were cached.
+    pub cache_hit_rate: f64,
+}
+
+/// Writes per-generation statistics to a file, as CSV or JSON lines.
+pub struct Trace {
+    buf: BufWriter<File>,
+    // The protein currently being optimised.
+    protein: String,
+    host_names: Vec<String>,
+    json: bool,
+}
+
+impl Trace {
+    pub fn new(outfile: &str, host_names: &[String]) -> Trace {
+        // Use JSON lines if the file extension asks for it, and CSV otherwise.
+        let json = outfile.ends_with(".jsonl") || outfile.ends_with(".json");
+        let file = File::create(outfile).unwrap();
+        let mut trace = Trace {
+            buf: BufWriter::new(file),
+            protein: String::new(),
+            host_names: host_names.to_vec(),
+            json,
+        };
+        if !json {
+            let host_columns = c![format!(",best_ars_{}", i), for i in host_names].concat();
+            writeln!(
+                trace.buf,
+                "protein,generation,best,mean,median,worst{},diversity,unique,cache_hit_rate",
+                host_columns
+            )
+            .unwrap();
+        }
+        trace
+    }
+
+    pub fn set_protein(&mut self, protein: &str) {
+        self.protein = protein.to_string();
+    }
+
+    pub fn record(&mut self, stats: &GenerationStats) {
+        if self.json {
+            let host_best = self
+                .host_names
+                .iter()
+                .zip(stats.host_best.iter())
+                .map(|(name, score)| (name.clone(), json!(score)))
+                .collect::<serde_json::Map<String, serde_json::Value>>();
+            let line = json!({
+                "protein": self.protein,
+                "generation": stats.generation,
+                "best": stats.best,
+                "mean": stats.mean,
+                "median": stats.median,
+                "worst": stats.worst,
+                "host_best": host_best,
+                "diversity": stats.diversity,
+                "unique": stats.unique,
+                "cache_hit_rate": stats.cache_hit_rate,
+            });
+            writeln!(self.buf, "{}", line).unwrap();
+        } else {
+            let host_columns = c![format!(",{}", i), for i in &stats.host_best].concat();
+            writeln!(
+                self.buf,
+                "{},{},{},{},{},{}{},{},{},{}",
+                self.protein,
+                stats.generation,
+                stats.best,
+                stats.mean,
+                stats.median,
+                stats.worst,
+                host_columns,
+                stats.diversity,
+                stats.unique,
+                stats.cache_hit_rate
+            )
+            .unwrap();
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.buf.flush().unwrap();
+    }
+}
@@ -73,6 +73,15 @@ pub fn tt11() -> Vec<(&'static str, &'static str, &'static str)> {
     ]
 }
 
+// Gets a translation table by its NCBI ID. Tables 1 and 11 assign the same amino acid to every
+// codon, and differ only in their start codons, so both use the same table.
+pub fn by_id(id: u32) -> Option<Vec<(&'static str, &'static str, &'static str)>> {
+    match id {
+        1 | 11 => Some(tt11()),
+        _ => None,
+    }
+}
+
 #[allow(dead_code)]
 #[allow(unused_variables)]
 pub fn generate_maps(